@@ -0,0 +1,563 @@
+//! Offline stand-in for the BookBeat API, search API, status page and CDN,
+//! serving the books in `fixtures/catalog.json`.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::{json, Value};
+
+pub const USERNAME: &str = "reader@example.com";
+pub const PASSWORD: &str = "hunter2";
+pub const REFRESH_TOKEN: &str = "mock-refresh";
+pub const RATE_LIMIT_RESET: &str = "2030-01-01T00:00:00Z";
+
+const CATALOG: &str = include_str!("../fixtures/catalog.json");
+const TOKEN_PREFIX: &str = "Bearer mock-token-";
+
+/// 1x1 PNG served for every cover
+pub const COVER: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0x15, 0xC4,
+    0x89, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x44, 0x41, 0x54, 0x78, 0xDA, 0x63, 0x64, 0x60, 0xF8, 0x5F,
+    0x0F, 0x00, 0x02, 0x87, 0x01, 0x80, 0xEB, 0x47, 0xBA, 0x92, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+    0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+];
+
+/// Smallest file the MP4 tagger accepts: ftyp, an empty moov and some media data
+pub const AUDIOBOOK: &[u8] = &[
+    0x00, 0x00, 0x00, 0x1C, b'f', b't', b'y', b'p', b'M', b'4', b'A', b' ', 0x00, 0x00, 0x02, 0x00,
+    b'i', b's', b'o', b'm', b'i', b's', b'o', b'2', 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08,
+    b'm', b'o', b'o', b'v', 0x00, 0x00, 0x00, 0x10, b'm', b'd', b'a', b't', 0x01, 0x02, 0x03, 0x04,
+    0x05, 0x06, 0x07, 0x08,
+];
+
+/// Not a real EPUB, only the zip signature
+pub const EBOOK: &[u8] = b"PK\x03\x04mock epub";
+
+/// Counters shared with the test driving the server.
+#[derive(Default)]
+pub struct State {
+    /// Remaining licenses before `/license` answers 429
+    pub quota: AtomicUsize,
+    pub logins: AtomicUsize,
+    pub refreshes: AtomicUsize,
+    pub licenses: AtomicUsize,
+    /// `METHOD path?query` of every request
+    pub requests: Mutex<Vec<String>>,
+    /// Served by the status page, `{"type": "OK"}` unless replaced
+    pub status: Mutex<Value>,
+    /// Bytes the CDN leaves off the end of every file, for short transfers
+    pub truncate: AtomicUsize,
+    /// Licenses only link a stream, as for books that can't be downloaded
+    pub stream_only: AtomicBool,
+    tokens: AtomicUsize,
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    pub state: Arc<State>,
+}
+
+struct Catalog {
+    base: String,
+    user: Value,
+    books: Vec<Value>,
+    series: Vec<Value>,
+}
+
+impl MockServer {
+    /// Start on a random local port with 200 licenses available.
+    pub async fn start() -> Self {
+        Self::with_quota(200).await
+    }
+
+    pub async fn with_quota(quota: usize) -> Self {
+        Self::bind("127.0.0.1:0".parse().unwrap(), quota).await
+    }
+
+    pub async fn bind(addr: SocketAddr, quota: usize) -> Self {
+        let listener = std::net::TcpListener::bind(addr).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let state = Arc::new(State {
+            quota: AtomicUsize::new(quota),
+            status: Mutex::new(json!({ "type": "OK" })),
+            ..Default::default()
+        });
+        let catalog = Arc::new(Catalog::load(format!("http://{addr}")));
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            let catalog = catalog.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    let catalog = catalog.clone();
+                    async move { Ok::<_, Infallible>(handle(&state, &catalog, request).await) }
+                }))
+            }
+        });
+
+        let server = Server::from_tcp(listener).unwrap().serve(make_service);
+        tokio::spawn(server);
+
+        Self { addr, state }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Base URL to use for the API, search and status hosts.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Client logged in as the mock user.
+    #[cfg(feature = "bookbeat")]
+    pub async fn client(&self) -> bookbeat::client::Client {
+        let config = bookbeat::client::ClientConfig::with_host(&self.url());
+        bookbeat::client::Client::login_with(config, USERNAME, PASSWORD)
+            .await
+            .unwrap()
+    }
+}
+
+impl Catalog {
+    fn load(base: String) -> Self {
+        let mut catalog: Value = serde_json::from_str(CATALOG).unwrap();
+
+        let mut books = catalog["books"].as_array().unwrap().clone();
+        for book in &mut books {
+            let cover = format!("{}{}", base, book["cover"].as_str().unwrap());
+            book["cover"] = Value::String(cover);
+        }
+
+        Self {
+            base,
+            user: catalog["user"].take(),
+            books,
+            series: catalog["series"].as_array().unwrap().clone(),
+        }
+    }
+
+    fn book(&self, id: u64) -> Option<&Value> {
+        self.books.iter().find(|book| book["id"] == id)
+    }
+
+    fn by_isbn(&self, isbn: &str) -> Option<(&Value, &Value)> {
+        self.books.iter().find_map(|book| {
+            book["editions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|edition| edition["isbn"] == isbn)
+                .map(|edition| (book, edition))
+        })
+    }
+
+    /* Series entry of a book page */
+    fn series_of(&self, id: u64) -> Value {
+        self.series
+            .iter()
+            .find_map(|series| {
+                let part = series["parts"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find(|part| part["book"] == id)?;
+                Some(json!({
+                    "id": series["id"],
+                    "name": series["name"],
+                    "partnumber": part["partnumber"],
+                }))
+            })
+            .unwrap_or(Value::Null)
+    }
+
+    /* The search representation of a book */
+    fn search_book(&self, book: &Value) -> Value {
+        let isbn = |format: &str| {
+            book["editions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|edition| edition["format"] == format)
+                .map(|edition| edition["isbn"].clone())
+                .unwrap_or(Value::Null)
+        };
+
+        json!({
+            "id": book["id"],
+            "title": book["title"],
+            "image": book["cover"],
+            "author": book["author"],
+            "grade": book["grade"],
+            "language": book["language"],
+            "audiobookisbn": isbn("audioBook"),
+            "ebookisbn": isbn("eBook"),
+            "published": book["published"],
+            "erotic": book["erotic"] == true,
+        })
+    }
+}
+
+fn query(request: &Request<Body>) -> Vec<(String, String)> {
+    let query = request.uri().query().unwrap_or("");
+    url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn param<'a>(query: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    query
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+fn page(query: &[(String, String)]) -> (usize, usize) {
+    let offset = param(query, "offset")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let limit = param(query, "limit")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(50);
+    (offset, limit)
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/hal+json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    respond(status, json!({ "Message": message }))
+}
+
+fn contains(haystack: &Value, needle: &str) -> bool {
+    haystack
+        .as_str()
+        .unwrap_or_default()
+        .to_lowercase()
+        .contains(&needle.to_lowercase())
+}
+
+/* Filter by language and erotic content, newest first, paged */
+fn search<'a>(
+    catalog: &Catalog,
+    query: &[(String, String)],
+    hits: impl Iterator<Item = &'a Value>,
+) -> Response<Body> {
+    let languages: Vec<&str> = query
+        .iter()
+        .filter(|(k, _)| k == "language")
+        .map(|(_, v)| v.as_str())
+        .collect();
+    let includeerotic = param(query, "includeerotic") != Some("false");
+
+    let mut hits: Vec<&Value> = hits
+        .filter(|book| {
+            languages.is_empty()
+                || languages
+                    .iter()
+                    .any(|language| book["language"] == *language)
+        })
+        .filter(|book| includeerotic || book["erotic"] != true)
+        .collect();
+
+    /* Newest first, like sortby=publishdate */
+    hits.sort_by(|a, b| b["published"].as_str().cmp(&a["published"].as_str()));
+
+    let (offset, limit) = page(query);
+    let books: Vec<Value> = hits
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|book| catalog.search_book(book))
+        .collect();
+
+    respond(
+        StatusCode::OK,
+        json!({ "count": hits.len(), "_embedded": { "books": books } }),
+    )
+}
+
+fn login(state: &State) -> Value {
+    let token = state.tokens.fetch_add(1, Ordering::SeqCst);
+    json!({
+        "refreshtoken": REFRESH_TOKEN,
+        "token": format!("mock-token-{token}"),
+        "expiresin": 3600,
+    })
+}
+
+async fn handle(state: &State, catalog: &Catalog, request: Request<Body>) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    state
+        .requests
+        .lock()
+        .unwrap()
+        .push(match request.uri().query() {
+            Some(query) => format!("{method} {path}?{query}"),
+            None => format!("{method} {path}"),
+        });
+
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    /* Endpoints that don't need a token */
+    match (&method, segments.as_slice()) {
+        (&Method::GET, ["api", "prod", "status"]) => {
+            let status = state.status.lock().unwrap().clone();
+            return respond(StatusCode::OK, status);
+        }
+        (&Method::POST, ["api", "login"]) => {
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+            if body["username"] != USERNAME || body["password"] != PASSWORD {
+                return error(StatusCode::UNAUTHORIZED, "Invalid username or password");
+            }
+            state.logins.fetch_add(1, Ordering::SeqCst);
+            return respond(StatusCode::OK, login(state));
+        }
+        (&Method::GET, ["covers", _]) => {
+            return Response::builder()
+                .header("content-type", "image/png")
+                .body(Body::from(COVER))
+                .unwrap();
+        }
+        (&Method::GET, ["cdn", isbn]) => {
+            let file = match catalog.by_isbn(isbn) {
+                Some((_, edition)) if edition["format"] == "audioBook" => AUDIOBOOK,
+                Some(_) => EBOOK,
+                None => return error(StatusCode::NOT_FOUND, "Not found"),
+            };
+            let truncate = state.truncate.load(Ordering::SeqCst).min(file.len());
+            return Response::new(Body::from(&file[..file.len() - truncate]));
+        }
+        _ => {}
+    }
+
+    let authorized = request
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(TOKEN_PREFIX));
+    if !authorized {
+        return error(StatusCode::UNAUTHORIZED, "Authorization has been denied");
+    }
+
+    let query = query(&request);
+
+    match (&method, segments.as_slice()) {
+        (&Method::POST, ["api", "login", "refresh"]) => {
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+            if body["refreshtoken"] != REFRESH_TOKEN {
+                return error(StatusCode::UNAUTHORIZED, "Invalid refresh token");
+            }
+            state.refreshes.fetch_add(1, Ordering::SeqCst);
+            respond(StatusCode::OK, login(state))
+        }
+        (&Method::GET, ["api", "users"]) => respond(StatusCode::OK, catalog.user.clone()),
+        (&Method::GET, ["api", "search", "books"])
+        | (&Method::GET, ["api", "tabsearch", "books"]) => {
+            let author = param(&query, "author");
+            let narrator = param(&query, "narrator");
+            let text = param(&query, "query");
+
+            let hits = catalog
+                .books
+                .iter()
+                .filter(|book| {
+                    author
+                        .iter()
+                        .all(|author| contains(&book["author"], author))
+                })
+                .filter(|book| {
+                    narrator
+                        .iter()
+                        .all(|narrator| contains(&book["narrator"], narrator))
+                })
+                .filter(|book| {
+                    text.iter().all(|&text| {
+                        contains(&book["title"], text)
+                            || contains(&book["author"], text)
+                            || book["editions"]
+                                .as_array()
+                                .unwrap()
+                                .iter()
+                                .any(|edition| edition["isbn"] == text)
+                    })
+                });
+
+            search(catalog, &query, hits)
+        }
+        (&Method::GET, ["api", "genres", _market]) => {
+            let mut genres: Vec<&Value> = Vec::new();
+            for genre in catalog
+                .books
+                .iter()
+                .flat_map(|book| book["genres"].as_array().unwrap())
+            {
+                if !genres
+                    .iter()
+                    .any(|known| known["genreid"] == genre["genreid"])
+                {
+                    genres.push(genre);
+                }
+            }
+            genres.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+            respond(
+                StatusCode::OK,
+                json!({ "count": genres.len(), "_embedded": { "genres": genres } }),
+            )
+        }
+        (&Method::GET, ["api", "genres", _market, id, "books"]) => {
+            let id: u64 = id.parse().unwrap_or_default();
+            let hits = catalog.books.iter().filter(|book| {
+                book["genres"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .any(|genre| genre["genreid"] == id)
+            });
+
+            search(catalog, &query, hits)
+        }
+        (&Method::GET, ["api", "books", market, id]) => {
+            /* Editions listing markets are only sold there */
+            let book = id.parse().ok().and_then(|id| catalog.book(id)).map(|book| {
+                let mut book = book.clone();
+                book["erotic"] = json!(book["erotic"] == true);
+                book["series"] = catalog.series_of(book["id"].as_u64().unwrap());
+                book["editions"].as_array_mut().unwrap().retain(|edition| {
+                    match edition["markets"].as_array() {
+                        Some(markets) => markets.iter().any(|m| m == market),
+                        None => true,
+                    }
+                });
+                book
+            });
+            match book {
+                Some(book) if !book["editions"].as_array().unwrap().is_empty() => {
+                    respond(StatusCode::OK, book)
+                }
+                _ => error(StatusCode::NOT_FOUND, "Book not found"),
+            }
+        }
+        (&Method::GET, ["api", "series", id]) => {
+            let series = id
+                .parse::<u64>()
+                .ok()
+                .and_then(|id| catalog.series.iter().find(|series| series["id"] == id));
+            let Some(series) = series else {
+                return error(StatusCode::NOT_FOUND, "Series not found");
+            };
+
+            let all = series["parts"].as_array().unwrap();
+            let (offset, limit) = page(&query);
+            let parts: Vec<Value> = all
+                .iter()
+                .skip(offset)
+                .take(limit)
+                .map(|part| {
+                    let book = catalog.book(part["book"].as_u64().unwrap()).unwrap();
+                    json!({
+                        "partnumber": part["partnumber"],
+                        "_embedded": { "book": catalog.search_book(book) },
+                    })
+                })
+                .collect();
+
+            respond(
+                StatusCode::OK,
+                json!({
+                    "count": all.len(),
+                    "id": series["id"],
+                    "name": series["name"],
+                    "description": series["description"],
+                    "_embedded": { "parts": parts },
+                }),
+            )
+        }
+        (&Method::GET, ["api", "content", isbn, "license"]) => license(state, catalog, isbn),
+        _ => error(StatusCode::NOT_FOUND, "No HTTP resource was found"),
+    }
+}
+
+fn license(state: &State, catalog: &Catalog, isbn: &str) -> Response<Body> {
+    let Some((_, edition)) = catalog.by_isbn(isbn) else {
+        return error(StatusCode::NOT_FOUND, "Content not found");
+    };
+
+    let remaining = state
+        .quota
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |quota| {
+            quota.checked_sub(1)
+        });
+
+    let mut headers = HashMap::new();
+    headers.insert("x-rate-limit-limit", "1d".to_owned());
+    headers.insert("x-rate-limit-reset", RATE_LIMIT_RESET.to_owned());
+
+    let mut response = match remaining {
+        Ok(before) => {
+            state.licenses.fetch_add(1, Ordering::SeqCst);
+            headers.insert("x-rate-limit-remaining", (before - 1).to_string());
+
+            let size = if edition["format"] == "audioBook" {
+                AUDIOBOOK.len()
+            } else {
+                EBOOK.len()
+            };
+            let link = json!({ "href": format!("{}/cdn/{}", catalog.base, isbn) });
+            let (download, stream) = if state.stream_only.load(Ordering::SeqCst) {
+                (Value::Null, link)
+            } else {
+                (link, Value::Null)
+            };
+            respond(
+                StatusCode::OK,
+                json!({
+                    "isbn": isbn,
+                    "assetid": format!("asset-{isbn}"),
+                    "source": "mock",
+                    "filesize": size,
+                    "tracks": [],
+                    "_links": {
+                        "download": download,
+                        "stream": stream,
+                    },
+                }),
+            )
+        }
+        Err(_) => {
+            headers.insert("x-rate-limit-remaining", "0".to_owned());
+            error(StatusCode::TOO_MANY_REQUESTS, "limit exceeded")
+        }
+    };
+
+    for (name, value) in headers {
+        response.headers_mut().insert(name, value.parse().unwrap());
+    }
+
+    response
+}
@@ -0,0 +1,15 @@
+use bookbeat_mock::{MockServer, PASSWORD, USERNAME};
+
+/* Usage: bookbeat-mock [ADDRESS] [QUOTA] */
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_owned());
+    let quota = args.next().map_or(200, |quota| quota.parse().unwrap());
+
+    let server = MockServer::bind(addr.parse().unwrap(), quota).await;
+    println!("Mock BookBeat API on {}", server.url());
+    println!("Login with {USERNAME} / {PASSWORD}");
+
+    tokio::signal::ctrl_c().await.unwrap();
+}
@@ -3,8 +3,23 @@ pub enum Error {
     Api(u16, String),
     Cdn(u16, String),
     Status(String),
+    Cover(String),
+    Hook(String),
+    Cassette(String),
+    DiskSpace(String),
+    /// The transfer ended early, with the bytes received and expected
+    Truncated(u64, u64),
+    /// The license of the ISBN came without a download link
+    NoDownload(String),
+    Cancelled,
+    /// The download window closed during the transfer
+    WindowClosed,
+    BookId(usize),
     Reqwest(reqwest::Error),
     Serde(serde_json::Error),
+    Image(image::ImageError),
+    Tag(mp4ameta::Error),
+    Io(std::io::Error),
 }
 
 impl Error {
@@ -14,6 +29,21 @@ impl Error {
     pub fn from_serde(error: serde_json::Error) -> Self {
         Self::Serde(error)
     }
+    pub fn from_image(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+    pub fn from_tag(error: mp4ameta::Error) -> Self {
+        Self::Tag(error)
+    }
+    pub fn from_io(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+
+    /// The licensing limit of the subscription is used up, rather than
+    /// some endpoint being rate limited.
+    pub fn is_quota_exceeded(&self) -> bool {
+        matches!(self, Self::Api(429, message) if message.to_ascii_lowercase().contains("limit exceeded"))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,51 @@
+use crate::{
+    api::{Error, Result},
+    client::{Book, BookFormat, Client, Edition},
+    locale::Market,
+};
+
+/// A book as sold in one market.
+pub struct Availability {
+    pub market: Market,
+    /// None when the market doesn't carry the book at all
+    pub book: Option<Book>,
+}
+
+impl Availability {
+    pub fn editions(&self, format: BookFormat) -> impl Iterator<Item = &Edition> {
+        self.book
+            .iter()
+            .flat_map(|book| &book.editions)
+            .filter(move |edition| edition.format == format)
+    }
+
+    pub fn has(&self, format: BookFormat) -> bool {
+        self.editions(format).next().is_some()
+    }
+}
+
+/// Look up a book in each of `markets`, in order.
+pub async fn compare(client: &Client, id: u32, markets: &[Market]) -> Result<Vec<Availability>> {
+    let mut availability = Vec::with_capacity(markets.len());
+    for &market in markets {
+        let book = match client.for_market(market).books(id).await {
+            Ok(book) => Some(book),
+            Err(Error::Api(404, _)) => None,
+            Err(err) => return Err(err),
+        };
+        availability.push(Availability { market, book });
+    }
+    Ok(availability)
+}
+
+/// The market the client is set to followed by every other one.
+pub fn markets_from(home: Market) -> Vec<Market> {
+    std::iter::once(home)
+        .chain(Market::all().filter(|&market| market != home))
+        .collect()
+}
+
+/// First market offering `format`, keeping the order of the comparison.
+pub fn pick(availability: &[Availability], format: BookFormat) -> Option<&Availability> {
+    availability.iter().find(|entry| entry.has(format))
+}
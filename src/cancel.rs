@@ -0,0 +1,115 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    Running,
+    /// Finish the current file, start nothing new
+    Stopping,
+    /// Give up the current file as well
+    Aborting,
+}
+
+/// Tells downloads to stop, e.g. on Ctrl-C. Clones share the state.
+#[derive(Clone)]
+pub struct Cancel {
+    state: Arc<watch::Sender<State>>,
+    /// Partial file of the running transfer, for exiting at once
+    current: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl Default for Cancel {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(watch::channel(State::Running).0),
+            current: Default::default(),
+        }
+    }
+}
+
+impl Cancel {
+    pub fn stop(&self) {
+        self.raise(State::Stopping);
+    }
+
+    pub fn abort(&self) {
+        self.raise(State::Aborting);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.state.borrow() >= State::Stopping
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        *self.state.borrow() == State::Aborting
+    }
+
+    /// Resolves once no new work should be started.
+    pub async fn stopped(&self) {
+        self.reached(State::Stopping).await
+    }
+
+    /// Resolves once the current file should be given up.
+    pub async fn aborted(&self) {
+        self.reached(State::Aborting).await
+    }
+
+    /// Set while a transfer writes to `partial`, cleared once it is done.
+    pub fn working_on(&self, partial: Option<&Path>) {
+        *self.current.lock().unwrap() = partial.map(Path::to_owned);
+    }
+
+    /// Stop on the first Ctrl-C, abort on the second and exit on the third.
+    pub fn on_ctrl_c(&self) {
+        let cancel = self.clone();
+        tokio::spawn(async move {
+            let mut presses = 0;
+            while tokio::signal::ctrl_c().await.is_ok() {
+                presses += 1;
+                match presses {
+                    1 => {
+                        log::warn!(
+                            "Stopping after the current file, press Ctrl-C again to abort it"
+                        );
+                        cancel.stop();
+                    }
+                    2 => {
+                        log::warn!("Aborting the current file, press Ctrl-C again to exit at once");
+                        cancel.abort();
+                    }
+                    _ => {
+                        /* Nothing gets to clean up after this */
+                        if let Some(partial) = cancel.current.lock().unwrap().as_ref() {
+                            log::warn!("Exiting, leaving {} behind", partial.display());
+                        }
+                        log::warn!("Exiting without running --on-finish");
+                        std::process::exit(130)
+                    }
+                }
+            }
+        });
+    }
+
+    /* States only ever advance, so never go back */
+    fn raise(&self, state: State) {
+        self.state.send_if_modified(|current| {
+            let raised = state > *current;
+            if raised {
+                *current = state;
+            }
+            raised
+        });
+    }
+
+    async fn reached(&self, state: State) {
+        let mut receiver = self.state.subscribe();
+        while *receiver.borrow_and_update() < state {
+            /* The sender lives as long as self */
+            let _ = receiver.changed().await;
+        }
+    }
+}
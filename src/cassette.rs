@@ -0,0 +1,256 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use serde_json::Value;
+
+use crate::{
+    api::{Error, Result},
+    redact,
+};
+
+/// Directory the API traffic of a `Client` is recorded to or replayed from.
+#[derive(Debug, Clone)]
+pub enum Cassette {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub(crate) struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct RecordedRequest {
+    method: String,
+    /// Path and query, the host is left out so cassettes replay against any configuration
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Parsed when the body is JSON, so cassettes stay readable and editable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+pub(crate) enum Tape {
+    Off,
+    Record {
+        dir: PathBuf,
+        count: AtomicUsize,
+    },
+    Replay {
+        interactions: Vec<Interaction>,
+        played: Mutex<Vec<bool>>,
+    },
+}
+
+impl Tape {
+    pub(crate) fn new(cassette: Option<&Cassette>) -> Result<Self> {
+        match cassette {
+            None => Ok(Self::Off),
+            Some(Cassette::Record(dir)) => {
+                std::fs::create_dir_all(dir).map_err(Error::from_io)?;
+                /* Continue numbering when recording into an existing cassette */
+                let count = interaction_files(dir)?.len();
+                Ok(Self::Record {
+                    dir: dir.to_owned(),
+                    count: AtomicUsize::new(count),
+                })
+            }
+            Some(Cassette::Replay(dir)) => {
+                let mut interactions = Vec::new();
+                for path in interaction_files(dir)? {
+                    let data = std::fs::read(&path).map_err(Error::from_io)?;
+                    interactions.push(serde_json::from_slice(&data).map_err(Error::from_serde)?);
+                }
+                let played = Mutex::new(vec![false; interactions.len()]);
+                Ok(Self::Replay {
+                    interactions,
+                    played,
+                })
+            }
+        }
+    }
+
+    pub(crate) async fn send(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response> {
+        match self {
+            Self::Off => client.execute(request).await.map_err(Error::from_reqwest),
+            Self::Record { dir, count } => {
+                let recorded = record_request(&request);
+                let response = client.execute(request).await.map_err(Error::from_reqwest)?;
+
+                let status = response.status().as_u16();
+                let headers: BTreeMap<String, String> = response
+                    .headers()
+                    .iter()
+                    .filter(|(name, _)| name.as_str() != "set-cookie")
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                    })
+                    .collect();
+                let body = response.bytes().await.map_err(Error::from_reqwest)?;
+
+                let interaction = Interaction {
+                    request: recorded,
+                    response: RecordedResponse::new(status, headers.clone(), &body),
+                };
+
+                let index = count.fetch_add(1, Ordering::SeqCst);
+                let path = dir.join(file_name(index, &interaction.request));
+                let data = serde_json::to_vec_pretty(&interaction).map_err(Error::from_serde)?;
+                tokio::fs::write(path, data).await.map_err(Error::from_io)?;
+
+                /* The caller gets the unredacted response */
+                build_response(status, &headers, body.to_vec())
+            }
+            Self::Replay {
+                interactions,
+                played,
+            } => {
+                let wanted = record_request(&request);
+                let matches = |interaction: &Interaction| {
+                    interaction.request.method == wanted.method
+                        && interaction.request.url == wanted.url
+                };
+
+                /* Serve recordings of the same request in order, repeating the last one */
+                let mut played = played.lock().unwrap();
+                let index = interactions
+                    .iter()
+                    .enumerate()
+                    .position(|(index, interaction)| !played[index] && matches(interaction))
+                    .or_else(|| interactions.iter().rposition(matches))
+                    .ok_or_else(|| {
+                        Error::Cassette(format!(
+                            "No recording for {} {}",
+                            wanted.method, wanted.url
+                        ))
+                    })?;
+                played[index] = true;
+
+                interactions[index].response.to_response()
+            }
+        }
+    }
+}
+
+impl RecordedResponse {
+    fn new(status: u16, headers: BTreeMap<String, String>, body: &[u8]) -> Self {
+        let (json, text) = match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) => {
+                redact::json(&mut json);
+                (Some(json), None)
+            }
+            Err(_) if body.is_empty() => (None, None),
+            Err(_) => (None, Some(String::from_utf8_lossy(body).into_owned())),
+        };
+
+        Self {
+            status,
+            headers,
+            json,
+            text,
+        }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        match (&self.json, &self.text) {
+            (Some(json), _) => json.to_string().into_bytes(),
+            (None, Some(text)) => text.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    fn to_response(&self) -> Result<reqwest::Response> {
+        build_response(self.status, &self.headers, self.body())
+    }
+}
+
+fn build_response(
+    status: u16,
+    headers: &BTreeMap<String, String>,
+    body: Vec<u8>,
+) -> Result<reqwest::Response> {
+    let mut builder = http::Response::builder().status(status);
+    for (name, value) in headers {
+        /* The body is already decoded and may be re-encoded, the original framing no longer applies */
+        if name == "content-length" || name == "content-encoding" || name == "transfer-encoding" {
+            continue;
+        }
+        builder = builder.header(name, value);
+    }
+
+    let response = builder
+        .body(body)
+        .map_err(|err| Error::Cassette(err.to_string()))?;
+
+    Ok(reqwest::Response::from(response))
+}
+
+fn record_request(request: &reqwest::Request) -> RecordedRequest {
+    let url = request.url();
+    let url = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .and_then(|body| serde_json::from_slice::<Value>(body).ok())
+        .map(|mut body| {
+            redact::json(&mut body);
+            body
+        });
+
+    RecordedRequest {
+        method: request.method().to_string(),
+        url,
+        body,
+    }
+}
+
+fn file_name(index: usize, request: &RecordedRequest) -> String {
+    let path = request.url.split('?').next().unwrap_or_default();
+    let slug: String = path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let slug = slug.trim_matches('-');
+    format!(
+        "{:04}-{}-{}.json",
+        index,
+        request.method.to_lowercase(),
+        &slug[..slug.len().min(60)]
+    )
+}
+
+fn interaction_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(Error::from_io)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
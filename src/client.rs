@@ -1,24 +1,153 @@
-use crate::api::{Error, Result};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{
+    api::{Error, Result},
+    cassette::{Cassette, Tape},
+    locale::{Language, Market},
+    redact,
+    status::{Health, ServiceStatus, StatusPolicy},
+};
 
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Response,
 };
 
-type DateTime = chrono::DateTime<chrono::Utc>;
+pub type DateTime = chrono::DateTime<chrono::Utc>;
+
+/// Search results carry ids as `usize`, book lookups take `u32`.
+pub fn book_id(id: usize) -> Result<u32> {
+    id.try_into().map_err(|_| Error::BookId(id))
+}
 
 const USER_AGENT: &str = "BookBeat 9.7.1 phone OnePlus Dalvik/2.1.0 (Linux; U; Android 10; ONEPLUS A5000 Build/QKQ1.191014.012)";
-const API_STATUS: &str = "https://status.bookbeat.com/api/prod/status/";
-const LOGIN_URL: &str = "https://api.bookbeat.com/api/login";
-const REFRESH_URL: &str = "https://api.bookbeat.com/api/login/refresh";
-const USERS_URL: &str = "https://api.bookbeat.com/api/users";
-const TABSEARCH_BOOKS_URL: &str = "https://search-api.bookbeat.com/api/tabsearch/books";
-const SEARCH_BOOKS_URL: &str = "https://api.bookbeat.com/api/search/books";
+const API_HOST: &str = "https://api.bookbeat.com";
+const SEARCH_HOST: &str = "https://search-api.bookbeat.com";
+const STATUS_HOST: &str = "https://status.bookbeat.com";
+const API_STATUS: &str = "/api/prod/status/";
+const LOGIN_URL: &str = "/api/login";
+const REFRESH_URL: &str = "/api/login/refresh";
+const USERS_URL: &str = "/api/users";
+const TABSEARCH_BOOKS_URL: &str = "/api/tabsearch/books";
+const SEARCH_BOOKS_URL: &str = "/api/search/books";
+const RATE_LIMIT_HEADERS: [&str; 3] = [
+    "x-rate-limit-limit",
+    "x-rate-limit-remaining",
+    "x-rate-limit-reset",
+];
+
+/// Hosts the client talks to. Defaults to the production services.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub api_host: String,
+    pub search_host: String,
+    pub status_host: String,
+    /// Record the API traffic to, or serve it from, a directory
+    pub cassette: Option<Cassette>,
+    pub transport: Transport,
+    /// Whether to log in while the service is degraded or down
+    pub status_policy: StatusPolicy,
+    /// Storefront for every request, including book lookups
+    pub market: Market,
+}
 
-#[derive(serde::Deserialize, Debug)]
-struct Status {
-    #[serde(rename = "type")]
-    typ: String,
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            api_host: API_HOST.to_owned(),
+            search_host: SEARCH_HOST.to_owned(),
+            status_host: STATUS_HOST.to_owned(),
+            cassette: None,
+            transport: Transport::default(),
+            status_policy: StatusPolicy::default(),
+            market: Market::default(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Serve everything from a single host, e.g. staging or a local stand-in server.
+    pub fn with_host(host: &str) -> Self {
+        Self {
+            api_host: host.to_owned(),
+            search_host: host.to_owned(),
+            status_host: host.to_owned(),
+            cassette: None,
+            transport: Transport::default(),
+            status_policy: StatusPolicy::default(),
+            market: Market::default(),
+        }
+    }
+
+    /// The defaults overridden by `BOOKBEAT_HOST` and the more specific
+    /// `BOOKBEAT_API_HOST`, `BOOKBEAT_SEARCH_HOST` and `BOOKBEAT_STATUS_HOST`.
+    pub fn from_env() -> Self {
+        let mut config = match std::env::var("BOOKBEAT_HOST") {
+            Ok(host) => Self::with_host(&host),
+            Err(_) => Self::default(),
+        };
+
+        if let Ok(host) = std::env::var("BOOKBEAT_API_HOST") {
+            config.api_host = host;
+        }
+        if let Ok(host) = std::env::var("BOOKBEAT_SEARCH_HOST") {
+            config.search_host = host;
+        }
+        if let Ok(host) = std::env::var("BOOKBEAT_STATUS_HOST") {
+            config.status_host = host;
+        }
+
+        config
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}{}", self.api_host.trim_end_matches('/'), path)
+    }
+
+    fn search(&self, path: &str) -> String {
+        format!("{}{}", self.search_host.trim_end_matches('/'), path)
+    }
+
+    fn status(&self, path: &str) -> String {
+        format!("{}{}", self.status_host.trim_end_matches('/'), path)
+    }
+}
+
+/// Proxy and extra trusted certificate, shared by the API client, the
+/// downloader and cover fetches. Without a proxy `HTTPS_PROXY` and
+/// `HTTP_PROXY` from the environment are used.
+#[derive(Debug, Clone, Default)]
+pub struct Transport {
+    /// `http://`, `https://`, `socks5://` or `socks5h://` URL
+    pub proxy: Option<String>,
+    /// PEM certificate to trust in addition to the system roots
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl Transport {
+    pub fn builder(&self) -> Result<reqwest::ClientBuilder> {
+        /* 15 Minute keepalive */
+        let keepalive = std::time::Duration::from_secs(15 * 60);
+
+        let mut builder = reqwest::ClientBuilder::new().tcp_keepalive(keepalive);
+
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy).map_err(Error::from_reqwest)?;
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = &self.ca_cert {
+            let pem = std::fs::read(path).map_err(Error::from_io)?;
+            let cert = reqwest::Certificate::from_pem(&pem).map_err(Error::from_reqwest)?;
+            builder = builder.add_root_certificate(cert);
+        }
+
+        Ok(builder)
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -101,7 +230,7 @@ pub struct SearchEmbedded {
     pub books: Vec<SearchBook>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct SearchBook {
     pub id: usize,
     pub title: String,
@@ -112,9 +241,13 @@ pub struct SearchBook {
     pub audiobookisbn: Option<String>,
     pub ebookisbn: Option<String>,
     pub published: DateTime,
+    /// Explicit content, left out by searches with `includeerotic=false`.
+    /// `None` when a response doesn't carry the flag.
+    #[serde(default)]
+    pub erotic: Option<bool>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct Book {
     pub id: usize,
     pub title: String,
@@ -127,15 +260,39 @@ pub struct Book {
     pub published: DateTime,
     pub genres: Vec<Genres>,
     pub editions: Vec<Edition>,
+    /// Explicit content, `None` when a response doesn't carry the flag.
+    #[serde(default)]
+    pub erotic: Option<bool>,
+    /// The series the book is part of, see `Client::series`.
+    #[serde(default)]
+    pub series: Option<BookSeries>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct BookSeries {
+    pub id: u32,
+    pub name: String,
+    pub partnumber: Option<u32>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct Genres {
     pub genreid: u32,
     pub name: String,
 }
 
 #[derive(serde::Deserialize, Debug)]
+pub struct GenreList {
+    pub count: usize,
+    pub _embedded: GenreListEmbedded,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct GenreListEmbedded {
+    pub genres: Vec<Genres>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct Edition {
     pub id: u32,
     pub isbn: String,
@@ -144,7 +301,7 @@ pub struct Edition {
     pub publisher: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BookFormat {
     #[serde(rename = "audioBook")]
     AudioBook,
@@ -152,6 +309,30 @@ pub enum BookFormat {
     EBook,
 }
 
+impl BookFormat {
+    /// File extension of the downloaded file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::AudioBook => "m4a",
+            Self::EBook => "epub",
+        }
+    }
+}
+
+impl std::str::FromStr for BookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "audiobook" | "m4a" => Ok(Self::AudioBook),
+            "ebook" | "epub" => Ok(Self::EBook),
+            _ => Err(format!(
+                "Unknown format \"{s}\", expected audiobook or ebook"
+            )),
+        }
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct Series {
     pub count: usize,
@@ -199,7 +380,7 @@ pub struct LicenseLinks {
     pub stream: Option<Link>,
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
 pub struct AuthToken {
     refreshtoken: String,
     token: String,
@@ -214,11 +395,15 @@ pub struct ApiError {
 
 pub struct Client {
     client: reqwest::Client,
+    config: ClientConfig,
+    tape: Arc<Tape>,
     token: AuthToken,
+    /// When the licensing quota renews, as last reported by the API
+    quota_reset: Arc<Mutex<Option<DateTime>>>,
 }
 
 impl Client {
-    fn inner() -> Result<reqwest::Client> {
+    fn inner(transport: &Transport, market: Market) -> Result<reqwest::Client> {
         let headers = [
             ("api-version", "9"),
             (
@@ -226,7 +411,7 @@ impl Client {
                 "4ac2d433-9126-4635-a769-553319a650c1 T05FUExVUyBPTkVQTFVTIEE1MDAw",
             ),
             ("bb-client", "BookBeatApp"),
-            ("bb-market", "Germany"),
+            ("bb-market", market.name()),
             ("accept-language", "en-US"),
         ];
 
@@ -238,52 +423,61 @@ impl Client {
             },
         );
 
-        /* 15 Minute keepalive */
-        let keepalive = std::time::Duration::from_secs(15 * 60);
-
-        let mut builder = reqwest::ClientBuilder::new()
+        let inner = transport
+            .builder()?
             .user_agent(USER_AGENT)
-            .tcp_keepalive(keepalive)
-            .default_headers(headers);
-
-        if cfg!(feature = "mitm") {
-            println!("Installing ssl proxy with certificate");
-            let proxy = reqwest::Proxy::all("http://127.0.0.1:8888").unwrap();
-            let pem = std::fs::read("cert.pem").unwrap();
-            let cert = reqwest::Certificate::from_pem(&pem).unwrap();
-            builder = builder.proxy(proxy).add_root_certificate(cert);
-        }
-
-        let inner = builder.build().map_err(Error::from_reqwest)?;
+            .default_headers(headers)
+            .build()
+            .map_err(Error::from_reqwest)?;
 
         Ok(inner)
     }
 
     pub async fn login(username: &str, password: &str) -> Result<Self> {
-        let client = Self::inner()?;
-
-        let status = Self::status(&client).await?;
+        Self::login_with(ClientConfig::default(), username, password).await
+    }
 
-        if status != "OK" {
-            return Err(Error::Status(status));
+    pub async fn login_with(config: ClientConfig, username: &str, password: &str) -> Result<Self> {
+        let client = Self::inner(&config.transport, config.market)?;
+        let tape = Arc::new(Tape::new(config.cassette.as_ref())?);
+
+        /* An unreachable status page only stops a strict login */
+        match Self::status(&client, &tape, &config).await {
+            Ok(status) => {
+                let health = status.health();
+                if !config.status_policy.allows(health) {
+                    return Err(Error::Status(status.summary()));
+                }
+                if health != Health::Operational {
+                    log::warn!("BookBeat reports {}, continuing", status.summary());
+                }
+            }
+            Err(err) if config.status_policy == StatusPolicy::Strict => return Err(err),
+            Err(err) => log::warn!("Failed to check the BookBeat status, continuing: {err:?}"),
         }
 
         let request = LoginRequest { username, password };
         let body = serde_json::to_vec(&request).map_err(Error::from_serde)?;
-        let response: Response = client
-            .post(LOGIN_URL)
+        let request = client
+            .post(config.api(LOGIN_URL))
             .body(body)
             .header("content-type", "application/json; charset=UTF-8")
             .header("accept", "application/hal+json")
-            .send()
-            .await
+            .build()
             .map_err(Error::from_reqwest)?;
+        let response: Response = Self::send(&client, &tape, request).await?;
 
         let login: Login = Self::parse(response).await?;
 
         let token = login.into_auth_token();
 
-        Ok(Self { client, token })
+        Ok(Self {
+            client,
+            config,
+            tape,
+            token,
+            quota_reset: Arc::default(),
+        })
     }
 
     async fn refresh_token(&mut self) -> Result<()> {
@@ -291,7 +485,8 @@ impl Client {
             refreshtoken: &self.token.refreshtoken,
         };
 
-        let login: Login = self.post_with_auth(REFRESH_URL, &body).await?;
+        let url = self.config.api(REFRESH_URL);
+        let login: Login = self.post_with_auth(&url, &body).await?;
 
         self.token = login.into_auth_token();
 
@@ -299,23 +494,111 @@ impl Client {
     }
 
     pub async fn from_token(token: AuthToken) -> Result<Self> {
-        let client = Self::inner()?;
+        Self::from_token_with(ClientConfig::default(), token).await
+    }
 
-        let mut client = Self { client, token };
+    pub async fn from_token_with(config: ClientConfig, token: AuthToken) -> Result<Self> {
+        let client = Self::inner(&config.transport, config.market)?;
+        let tape = Arc::new(Tape::new(config.cassette.as_ref())?);
 
-        if client.token.expiration < chrono::Utc::now() {
-            client.refresh_token().await?;
-        }
+        let mut client = Self {
+            client,
+            config,
+            tape,
+            token,
+            quota_reset: Arc::default(),
+        };
+        client.refresh_if_expired().await?;
 
         Ok(client)
     }
 
+    /// Renew an expired token, e.g. after waiting for the quota.
+    /// Returns whether it was renewed.
+    pub async fn refresh_if_expired(&mut self) -> Result<bool> {
+        if self.token.expiration >= chrono::Utc::now() {
+            return Ok(false);
+        }
+        self.refresh_token().await?;
+        Ok(true)
+    }
+
     pub fn extract_token(&self) -> &'_ AuthToken {
         &self.token
     }
 
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    pub fn market(&self) -> Market {
+        self.config.market
+    }
+
+    /// The same session making its requests for another market.
+    pub fn for_market(&self, market: Market) -> Self {
+        let mut config = self.config.clone();
+        config.market = market;
+        Self {
+            client: self.client.clone(),
+            config,
+            tape: self.tape.clone(),
+            token: self.token.clone(),
+            quota_reset: self.quota_reset.clone(),
+        }
+    }
+
+    /* Every API request goes through here, to the network or the cassette */
+    async fn send(
+        client: &reqwest::Client,
+        tape: &Tape,
+        request: reqwest::Request,
+    ) -> Result<Response> {
+        let method = request.method().clone();
+        let url = request.url().to_string();
+
+        if log::log_enabled!(log::Level::Trace) {
+            for (name, value) in request.headers() {
+                log::trace!("> {}: {}", name, redact::header(name.as_str(), value));
+            }
+            if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+                log::trace!("> {}", redact::body(body));
+            }
+        }
+
+        let start = Instant::now();
+        let response = tape.send(client, request).await;
+        let elapsed = start.elapsed().as_millis();
+
+        match &response {
+            Ok(response) => {
+                let limits: Vec<String> = RATE_LIMIT_HEADERS
+                    .iter()
+                    .filter_map(|&name| {
+                        let value = response.headers().get(name)?;
+                        Some(format!("{}={}", name, redact::header(name, value)))
+                    })
+                    .collect();
+                let message = format!(
+                    "{} {} {} in {}ms {}",
+                    method,
+                    url,
+                    response.status().as_u16(),
+                    elapsed,
+                    limits.join(" ")
+                );
+                log::debug!("{}", message.trim_end());
+            }
+            Err(err) => log::debug!("{} {} failed after {}ms: {:?}", method, url, elapsed, err),
+        }
+
+        response
+    }
+
     async fn parse<T: serde::de::DeserializeOwned>(response: reqwest::Response) -> Result<T> {
-        response.json().await.map_err(Error::from_reqwest)
+        let body = response.bytes().await.map_err(Error::from_reqwest)?;
+        log::trace!("< {}", redact::body(&body));
+        serde_json::from_slice(&body).map_err(Error::from_serde)
     }
 
     async fn post_with_auth<T: serde::Serialize, R: serde::de::DeserializeOwned>(
@@ -325,16 +608,17 @@ impl Client {
     ) -> Result<R> {
         let token = &self.token.token;
         let body = serde_json::to_vec(body).map_err(Error::from_serde)?;
-        let response = self
+        let request = self
             .client
             .post(url)
             .body(body)
             .header("content-type", "application/json; charset=UTF-8")
             .header("accept", "application/hal+json")
             .header("authorization", token)
-            .send()
-            .await
+            .header("bb-market", self.config.market.name())
+            .build()
             .map_err(Error::from_reqwest)?;
+        let response = Self::send(&self.client, &self.tape, request).await?;
 
         let status = response.status();
         if !status.is_success() {
@@ -350,18 +634,28 @@ impl Client {
         url: &str,
         query: Option<&[(&str, &str)]>,
     ) -> Result<R> {
+        let response = self.send_with_auth(url, query).await?;
+        Self::checked(response).await
+    }
+
+    async fn send_with_auth(&self, url: &str, query: Option<&[(&str, &str)]>) -> Result<Response> {
         let mut request = self
             .client
             .get(url)
             .header("authorization", &self.token.token)
-            .header("accept", "application/hal+json");
+            .header("accept", "application/hal+json")
+            .header("bb-market", self.config.market.name());
 
         if let Some(query) = query {
             request = request.query(query);
         }
 
-        let response = request.send().await.map_err(Error::from_reqwest)?;
+        let request = request.build().map_err(Error::from_reqwest)?;
+        Self::send(&self.client, &self.tape, request).await
+    }
 
+    /* The body of a successful response, otherwise the API's error */
+    async fn checked<R: serde::de::DeserializeOwned>(response: Response) -> Result<R> {
         let status = response.status();
         if !status.is_success() {
             let error: ApiError = Self::parse(response).await?;
@@ -371,19 +665,29 @@ impl Client {
         Self::parse(response).await
     }
 
-    async fn status(client: &reqwest::Client) -> Result<String> {
-        let response = client
-            .get(API_STATUS)
-            .send()
-            .await
-            .map_err(Error::from_reqwest)?;
-        let status: Status = response.json().await.map_err(Error::from_reqwest)?;
+    /// Fetch the status page without logging in.
+    pub async fn service_status(config: &ClientConfig) -> Result<ServiceStatus> {
+        let client = Self::inner(&config.transport, config.market)?;
+        let tape = Tape::new(config.cassette.as_ref())?;
+        Self::status(&client, &tape, config).await
+    }
 
-        Ok(status.typ)
+    async fn status(
+        client: &reqwest::Client,
+        tape: &Tape,
+        config: &ClientConfig,
+    ) -> Result<ServiceStatus> {
+        let request = client
+            .get(config.status(API_STATUS))
+            .build()
+            .map_err(Error::from_reqwest)?;
+        let response = Self::send(client, tape, request).await?;
+        Self::parse(response).await
     }
 
     pub async fn users(&self) -> Result<User> {
-        self.get_with_auth(USERS_URL, None).await
+        let url = self.config.api(USERS_URL);
+        self.get_with_auth(&url, None).await
     }
 
     pub async fn tabsearch_books(
@@ -391,8 +695,7 @@ impl Client {
         query: &str,
         offset: usize,
         limit: usize,
-        language: &[&str],
-        market: &str,
+        language: &[Language],
         kid: bool,
         includeerotic: bool,
     ) -> Result<Search> {
@@ -404,14 +707,15 @@ impl Client {
             ("query", query),
             ("offset", &offset),
             ("limit", &limit),
-            ("market", market),
+            ("market", self.config.market.name()),
             ("kid", kid),
             ("includeerotic", includeerotic),
         ];
-        for &language in language {
-            query.push(("language", language));
+        for language in language {
+            query.push(("language", language.name()));
         }
-        self.get_with_auth(TABSEARCH_BOOKS_URL, Some(&query)).await
+        let url = self.config.search(TABSEARCH_BOOKS_URL);
+        self.get_with_auth(&url, Some(&query)).await
     }
 
     pub async fn search(
@@ -420,7 +724,7 @@ impl Client {
         narrator: Option<&str>,
         offset: usize,
         limit: usize,
-        language: &[&str],
+        language: &[Language],
         includeerotic: bool,
     ) -> Result<Search> {
         let offset = offset.to_string();
@@ -442,28 +746,173 @@ impl Client {
             query.push(("narrator", narrator));
         }
 
-        for &language in language {
-            query.push(("language", language));
+        for language in language {
+            query.push(("language", language.name()));
         }
 
-        self.get_with_auth(SEARCH_BOOKS_URL, Some(&query)).await
+        let url = self.config.api(SEARCH_BOOKS_URL);
+        self.get_with_auth(&url, Some(&query)).await
     }
 
-    pub async fn books(&self, market: &str, id: u32) -> Result<Book> {
-        let url = format!("https://api.bookbeat.com/api/books/{market}/{id}");
+    /// Page through every search result.
+    pub async fn search_all(
+        &self,
+        author: Option<&str>,
+        narrator: Option<&str>,
+        language: &[Language],
+        includeerotic: bool,
+    ) -> Result<Vec<SearchBook>> {
+        Self::all_pages(|offset, limit| {
+            self.search(author, narrator, offset, limit, language, includeerotic)
+        })
+        .await
+    }
+
+    /// Genres of the current market.
+    pub async fn genres(&self) -> Result<Vec<Genres>> {
+        let market = self.config.market;
+        let url = self.config.api(&format!("/api/genres/{market}"));
+        let list: GenreList = self.get_with_auth(&url, None).await?;
+        Ok(list._embedded.genres)
+    }
+
+    pub async fn genre_books(
+        &self,
+        id: u32,
+        offset: usize,
+        limit: usize,
+        language: &[Language],
+        includeerotic: bool,
+    ) -> Result<Search> {
+        let market = self.config.market;
+        let url = self.config.api(&format!("/api/genres/{market}/{id}/books"));
+        let offset = offset.to_string();
+        let limit = limit.to_string();
+        let includeerotic = if includeerotic { "true" } else { "false" };
+        let mut query: Vec<(&str, &str)> = vec![
+            ("offset", &offset),
+            ("limit", &limit),
+            ("sortby", "publishdate"),
+            ("includeerotic", includeerotic),
+        ];
+        for language in language {
+            query.push(("language", language.name()));
+        }
+        self.get_with_auth(&url, Some(&query)).await
+    }
+
+    /// Page through every book of a genre.
+    pub async fn genre_books_all(
+        &self,
+        id: u32,
+        language: &[Language],
+        includeerotic: bool,
+    ) -> Result<Vec<SearchBook>> {
+        Self::all_pages(|offset, limit| {
+            self.genre_books(id, offset, limit, language, includeerotic)
+        })
+        .await
+    }
+
+    /* Request pages until one comes back short */
+    async fn all_pages<F, Fut>(mut page: F) -> Result<Vec<SearchBook>>
+    where
+        F: FnMut(usize, usize) -> Fut,
+        Fut: std::future::Future<Output = Result<Search>>,
+    {
+        const STEP: usize = 50;
+        let mut books = Vec::new();
+        let mut offset = 0;
+        loop {
+            let search = page(offset, STEP).await?;
+
+            let page = search._embedded.books;
+            let last = page.len() < STEP;
+            books.extend(page);
+
+            if last {
+                break;
+            }
+
+            offset += STEP;
+        }
+        Ok(books)
+    }
+
+    pub async fn books(&self, id: u32) -> Result<Book> {
+        let market = self.config.market;
+        let url = self.config.api(&format!("/api/books/{market}/{id}"));
         self.get_with_auth(&url, None).await
     }
 
+    /// Look up the book an ISBN belongs to. Searching finds books by ISBN,
+    /// but only lists one edition per format, so without an exact hit the
+    /// editions of the other hits are checked one by one.
+    pub async fn book_by_isbn(&self, isbn: &str) -> Result<Option<Book>> {
+        const LIMIT: usize = 10;
+        let search = self
+            .tabsearch_books(isbn, 0, LIMIT, &[], false, true)
+            .await?;
+
+        let hits = search._embedded.books;
+        let exact = |book: &SearchBook| {
+            book.audiobookisbn.as_deref() == Some(isbn) || book.ebookisbn.as_deref() == Some(isbn)
+        };
+
+        /* An exact hit is the book, no need to look at the others */
+        if let Some(hit) = hits.iter().find(|hit| exact(hit)) {
+            return self.books(book_id(hit.id)?).await.map(Some);
+        }
+
+        for hit in hits {
+            let book = self.books(book_id(hit.id)?).await?;
+            if book.editions.iter().any(|edition| edition.isbn == isbn) {
+                return Ok(Some(book));
+            }
+        }
+
+        Ok(None)
+    }
+
     pub async fn license(&self, isbn: &str) -> Result<License> {
-        let url = format!("https://api.bookbeat.com/api/content/{isbn}/license");
-        self.get_with_auth(&url, None).await
+        let url = self.config.api(&format!("/api/content/{isbn}/license"));
+        let response = self.send_with_auth(&url, None).await?;
+
+        let reset = response
+            .headers()
+            .get("x-rate-limit-reset")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        if reset.is_some() {
+            *self.quota_reset.lock().unwrap() = reset;
+        }
+
+        Self::checked(response).await
+    }
+
+    /// When the licensing quota renews, known after the first license request.
+    pub fn quota_reset(&self) -> Option<DateTime> {
+        *self.quota_reset.lock().unwrap()
     }
 
     pub async fn series(&self, id: u32, offset: usize, limit: usize) -> Result<Series> {
-        let url = format!("https://api.bookbeat.com/api/series/{id}");
+        let url = self.config.api(&format!("/api/series/{id}"));
         let offset = offset.to_string();
         let limit = limit.to_string();
         let query: [(&str, &str); 2] = [("offset", &offset), ("limit", &limit)];
         self.get_with_auth(&url, Some(&query)).await
     }
+
+    /// Fetch a series with all of its parts.
+    pub async fn series_all(&self, id: u32) -> Result<Series> {
+        const STEP: usize = 50;
+        let mut series = self.series(id, 0, STEP).await?;
+        let mut offset = STEP;
+        while series._embedded.parts.len() == offset {
+            let page = self.series(id, offset, STEP).await?;
+            series._embedded.parts.extend(page._embedded.parts);
+            offset += STEP;
+        }
+        Ok(series)
+    }
 }
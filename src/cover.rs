@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+
+use crate::api::{Error, Result};
+
+/* Query parameters the image CDN uses to downscale covers */
+const SIZE_PARAMS: [&str; 3] = ["w", "h", "width"];
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverFormat {
+    Jpeg,
+    Png,
+}
+
+impl CoverFormat {
+    /// Detect the image format by its magic bytes, ignoring whatever the
+    /// server claims in `content-type`.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match data {
+            [0xFF, 0xD8, 0xFF, ..] => Some(Self::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cover {
+    pub format: CoverFormat,
+    pub data: Vec<u8>,
+}
+
+impl Cover {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let format = CoverFormat::detect(&data)
+            .ok_or_else(|| Error::Cover("Unknown image format".to_owned()))?;
+        Ok(Self { format, data })
+    }
+
+    /// Re-encode as JPEG, shrinking the image to fit within `max_size` pixels
+    /// on its longest side.
+    pub fn normalise(self, max_size: Option<u32>) -> Result<Self> {
+        let mut image = image::load_from_memory(&self.data).map_err(Error::from_image)?;
+
+        if let Some(max_size) = max_size {
+            if image.width() > max_size || image.height() > max_size {
+                image = image.resize(max_size, max_size, image::imageops::FilterType::Lanczos3);
+            }
+        } else if self.format == CoverFormat::Jpeg {
+            return Ok(self);
+        }
+
+        let mut data = Vec::new();
+        let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY);
+        image
+            .to_rgb8()
+            .write_with_encoder(encoder)
+            .map_err(Error::from_image)?;
+
+        Ok(Self {
+            format: CoverFormat::Jpeg,
+            data,
+        })
+    }
+
+    pub fn to_mp4(&self) -> mp4ameta::Img<&[u8]> {
+        let format = match self.format {
+            CoverFormat::Jpeg => mp4ameta::ImgFmt::Jpeg,
+            CoverFormat::Png => mp4ameta::ImgFmt::Png,
+        };
+        mp4ameta::Img::new(format, &self.data)
+    }
+}
+
+/// Strip the downscaling parameters so the CDN serves the original upload.
+pub fn high_resolution(url: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_owned();
+    };
+
+    let query: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| !SIZE_PARAMS.contains(&key.as_ref()))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    parsed.into()
+}
+
+pub async fn fetch(client: &reqwest::Client, url: &str, max_size: Option<u32>) -> Result<Cover> {
+    let url = high_resolution(url);
+    let start = std::time::Instant::now();
+    let response = client.get(&url).send().await.map_err(Error::from_reqwest)?;
+
+    let status = response.status();
+    log::debug!(
+        "GET {} {} in {}ms",
+        url,
+        status.as_u16(),
+        start.elapsed().as_millis()
+    );
+    if !status.is_success() {
+        return Err(Error::Cdn(status.as_u16(), url));
+    }
+
+    let data = response.bytes().await.map_err(Error::from_reqwest)?;
+    let cover = Cover::from_bytes(data.to_vec())?;
+
+    if max_size.is_some() {
+        cover.normalise(max_size)
+    } else {
+        Ok(cover)
+    }
+}
+
+/// Location of the sidecar cover for `book`. Books stored in their own
+/// directory get a plain `cover.jpg`, otherwise the cover is named after the
+/// book file so neighbours don't overwrite each other.
+pub fn sidecar_path(book: &Path, own_directory: bool) -> PathBuf {
+    let extension = CoverFormat::Jpeg.extension();
+    if own_directory {
+        book.with_file_name(format!("cover.{extension}"))
+    } else {
+        book.with_extension(extension)
+    }
+}
+
+/// Write the cover as JPEG, the only format media servers look for.
+pub async fn write_sidecar(path: &Path, cover: &Cover) -> Result<()> {
+    let cover = cover.clone().normalise(None)?;
+    tokio::fs::write(path, &cover.data)
+        .await
+        .map_err(Error::from_io)
+}
@@ -0,0 +1,652 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::Datelike;
+use futures_util::stream::StreamExt;
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    api::{Error, Result},
+    cancel::Cancel,
+    client::{book_id, Book, BookFormat, Client, DateTime, SearchBook, Transport},
+    cover,
+    hook::{self, Hook},
+    library::{self, Library},
+    plan::WorkItem,
+    queue::Queue,
+    redact,
+    sidecar::Sidecar,
+    space::{self, Size},
+    throttle::{Rate, RateLimit, Window},
+};
+
+const PROGRESS_TEMPLATE: &str = "{wide_bar} [{bytes:10}/{total_bytes:10}] {eta:4}";
+
+/* Guesses for the batch check until the library has files of a format */
+const AUDIOBOOK_ESTIMATE: u64 = 300 << 20;
+const EBOOK_ESTIMATE: u64 = 5 << 20;
+
+/// Called with the ISBN, the bytes received so far and the expected size.
+pub type OnProgress = Arc<dyn Fn(&str, u64, u64) + Send + Sync>;
+
+/// Tagging information shared by search results and full book lookups.
+pub struct Metadata<'a> {
+    pub id: usize,
+    pub title: &'a str,
+    pub author: &'a str,
+    pub published: DateTime,
+    pub language: &'a str,
+    pub cover: Option<&'a str>,
+    pub series: Option<&'a str>,
+    pub part: Option<u32>,
+    /// Full details, if they were already looked up.
+    pub book: Option<&'a Book>,
+}
+
+impl<'a> Metadata<'a> {
+    pub fn with_series(self, series: &'a str, part: Option<u32>) -> Self {
+        Self {
+            series: Some(series),
+            part,
+            ..self
+        }
+    }
+}
+
+impl<'a> From<&'a SearchBook> for Metadata<'a> {
+    fn from(book: &'a SearchBook) -> Self {
+        Self {
+            id: book.id,
+            title: &book.title,
+            author: &book.author,
+            published: book.published,
+            language: &book.language,
+            cover: book.image.as_deref(),
+            series: None,
+            part: None,
+            book: None,
+        }
+    }
+}
+
+impl<'a> From<&'a Book> for Metadata<'a> {
+    fn from(book: &'a Book) -> Self {
+        Self {
+            id: book.id,
+            title: &book.title,
+            author: &book.author,
+            published: book.published,
+            language: &book.language,
+            cover: Some(book.cover.as_str()).filter(|cover| !cover.is_empty()),
+            series: book.series.as_ref().map(|series| series.name.as_str()),
+            part: book.series.as_ref().and_then(|series| series.partnumber),
+            book: Some(book),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Options {
+    /// Store every book in its own `Author/Title` folder.
+    pub book_dirs: bool,
+    /// Shrink covers to fit within this many pixels.
+    pub cover_size: Option<u32>,
+    /// Write the cover next to each downloaded book.
+    pub cover_sidecar: bool,
+    /// Write Audiobookshelf/Plex readable metadata next to each book.
+    pub metadata_sidecar: bool,
+    /// Run after every finished file.
+    pub on_download: Option<Hook>,
+    /// Run once after the whole batch.
+    pub on_finish: Option<Hook>,
+    /// Report progress here instead of drawing a progress bar.
+    pub on_progress: Option<OnProgress>,
+    /// Proxy and certificates for the CDN and cover downloads
+    pub transport: Transport,
+    /// Upper bound for all CDN transfers together
+    pub limit_rate: Option<Rate>,
+    /// Only transfer within this time of day
+    pub window: Option<Window>,
+    /// Bytes to leave free on the output filesystem
+    pub reserve: u64,
+    /// Only warn when the estimated sizes won't fit, the exact size of a
+    /// licensed file is checked regardless
+    pub ignore_estimate: bool,
+    /// Stops new transfers, or the current one as well, e.g. on Ctrl-C
+    pub cancel: Cancel,
+}
+
+/// Why a batch ended before its last file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Cancelled,
+    /// The licensing quota ran out, the pending files go to the queue
+    LimitReached,
+    /// The download window closed, the pending files go to the queue
+    WindowClosed,
+}
+
+/// Files a batch got done, and those it left when it stopped early.
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub completed: Vec<hook::Download>,
+    pub pending: Vec<WorkItem>,
+    pub stopped: Option<Stop>,
+}
+
+/// What an interrupted batch got done and what it left.
+pub fn summarize(completed: &[hook::Download], pending: &[WorkItem]) {
+    log::warn!(
+        "Stopped with {} file(s) downloaded and {} pending",
+        completed.len(),
+        pending.len()
+    );
+    for download in completed {
+        log::info!("  + {}", download.path.display());
+    }
+    for item in pending {
+        log::info!("  - {}", item.file_name());
+    }
+}
+
+pub struct Downloader {
+    path: PathBuf,
+    client: reqwest::Client,
+    style: indicatif::ProgressStyle,
+    options: Options,
+    limit: Option<RateLimit>,
+    library: Mutex<Library>,
+    completed: Mutex<Vec<hook::Download>>,
+}
+
+impl Downloader {
+    pub fn new(path: PathBuf, options: Options) -> Result<Self> {
+        let library = Library::load(&path)?;
+
+        let client = options
+            .transport
+            .builder()?
+            .user_agent("okhttp/4.10.0")
+            .build()
+            .map_err(Error::from_reqwest)?;
+
+        let style = indicatif::ProgressStyle::default_bar()
+            .template(PROGRESS_TEMPLATE)
+            .unwrap();
+
+        Ok(Self {
+            path,
+            client,
+            style,
+            limit: options.limit_rate.map(RateLimit::new),
+            options,
+            library: Mutex::new(library),
+            completed: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Shared with whoever should be able to stop the downloads.
+    pub fn cancel(&self) -> &Cancel {
+        &self.options.cancel
+    }
+
+    /// Whether the ISBN was downloaded into this folder before.
+    pub fn has(&self, isbn: &str) -> bool {
+        self.library.lock().unwrap().contains(isbn)
+    }
+
+    /// Expected size of a file of this format, going by the library.
+    pub fn estimate(&self, format: BookFormat) -> u64 {
+        let average = self.library.lock().unwrap().average_size(format);
+        average.unwrap_or(match format {
+            BookFormat::AudioBook => AUDIOBOOK_ESTIMATE,
+            BookFormat::EBook => EBOOK_ESTIMATE,
+        })
+    }
+
+    /// Fail unless the files likely fit into the output folder, keeping the reserve.
+    pub fn check_space(&self, formats: &[BookFormat]) -> Result<()> {
+        let needed = formats.iter().map(|&format| self.estimate(format)).sum();
+        let what = format!("for {} file(s)", formats.len());
+        self.check_estimate(&self.path, needed, &what)
+    }
+
+    fn check_estimate(&self, path: &Path, needed: u64, what: &str) -> Result<()> {
+        match space::check(path, needed, self.options.reserve, what) {
+            Err(Error::DiskSpace(message)) if self.options.ignore_estimate => {
+                log::warn!("Estimated {message}");
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn destination(&self, book: Option<&Metadata<'_>>, file_name: &str) -> PathBuf {
+        let mut path = self.path.clone();
+        if let (true, Some(book)) = (self.options.book_dirs, book) {
+            path.push(book.author.replace('/', "_"));
+            path.push(book.title.replace('/', "_"));
+        }
+        path.push(file_name.replace('/', "_"));
+        path
+    }
+
+    /// Download the items in order into `batch`, until one fails or the
+    /// batch is stopped, leaving the rest pending.
+    pub async fn download_all(
+        &self,
+        client: &Client,
+        items: &[WorkItem],
+        batch: &mut Batch,
+    ) -> Result<()> {
+        for (index, item) in items.iter().enumerate() {
+            let stop = match self.download_item(client, item).await {
+                Ok(download) => {
+                    batch.completed.push(download);
+                    continue;
+                }
+                Err(Error::Cancelled) => Stop::Cancelled,
+                Err(err) if err.is_quota_exceeded() => Stop::LimitReached,
+                Err(Error::WindowClosed) => Stop::WindowClosed,
+                Err(err) => return Err(err),
+            };
+            batch.pending.extend_from_slice(&items[index..]);
+            batch.stopped = Some(stop);
+            break;
+        }
+        Ok(())
+    }
+
+    /// Queue what the licensing limit or the window left of a batch for
+    /// `queue run`, or list what a cancelled one left.
+    pub fn settle(&self, client: &Client, batch: &Batch) -> Result<()> {
+        match batch.stopped {
+            Some(Stop::Cancelled) => {
+                summarize(&batch.completed, &batch.pending);
+                Ok(())
+            }
+            Some(stop) => self.defer(client, stop, &batch.pending),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the files to the queue in the output folder, for when the
+    /// licensing limit resets or the window opens again.
+    pub fn defer(&self, client: &Client, stop: Stop, items: &[WorkItem]) -> Result<()> {
+        let mut queue = Queue::load(&self.path)?;
+        if stop == Stop::LimitReached {
+            queue.reset = client.quota_reset();
+        }
+        let added = items
+            .iter()
+            .filter(|item| queue.push((*item).clone()))
+            .count();
+        queue.save()?;
+
+        match (stop, queue.reset) {
+            (Stop::LimitReached, Some(reset)) => log::warn!(
+                "Licensing limit reached until {}, queued {} file(s)",
+                reset.format("%Y-%m-%d %H:%M UTC"),
+                added
+            ),
+            (Stop::LimitReached, None) => {
+                log::warn!("Licensing limit reached, queued {} file(s)", added)
+            }
+            (Stop::WindowClosed, _) => {
+                log::warn!("The download window closed, queued {} file(s)", added)
+            }
+            (Stop::Cancelled, _) => log::warn!("Stopped, queued {} file(s)", added),
+        }
+        log::warn!("Resume with \"bookbeat queue run\"");
+        Ok(())
+    }
+
+    /// Download a planned file, licensed in the market of the item.
+    pub async fn download_item(&self, client: &Client, item: &WorkItem) -> Result<hook::Download> {
+        let other;
+        let client = if item.market == client.market() {
+            client
+        } else {
+            log::info!(
+                "Downloading \"{}\" from {}",
+                item.title().unwrap_or(&item.isbn),
+                item.market
+            );
+            other = client.for_market(item.market);
+            &other
+        };
+
+        self.download(
+            client,
+            &item.isbn,
+            item.format,
+            item.metadata().as_ref(),
+            &item.file_name(),
+        )
+        .await
+    }
+
+    pub async fn download(
+        &self,
+        client: &Client,
+        isbn: &str,
+        format: BookFormat,
+        book: Option<&Metadata<'_>>,
+        file_name: &str,
+    ) -> Result<hook::Download> {
+        let cancel = &self.options.cancel;
+        if cancel.is_stopped() {
+            return Err(Error::Cancelled);
+        }
+
+        /* Outside the window the license would expire while waiting */
+        if let Some(window) = &self.options.window {
+            tokio::select! {
+                _ = window.wait() => {}
+                _ = cancel.stopped() => return Err(Error::Cancelled),
+            }
+        }
+
+        /* Sizes are only known once licensed, a license spent on a file that won't fit is lost */
+        let path = self.destination(book, file_name);
+        self.check_estimate(
+            &path,
+            self.estimate(format),
+            &format!("for \"{file_name}\" (estimated)"),
+        )?;
+
+        /* Request link */
+        let license = client.license(isbn).await?;
+
+        let url = license
+            ._links
+            .download
+            .ok_or_else(|| Error::NoDownload(isbn.to_owned()))?;
+
+        let total = license.filesize as u64;
+        space::check(
+            &path,
+            total,
+            self.options.reserve,
+            &format!("for \"{file_name}\""),
+        )?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(Error::from_io)?;
+        }
+
+        let start = std::time::Instant::now();
+        let response = self
+            .client
+            .get(&url.href)
+            .send()
+            .await
+            .map_err(Error::from_reqwest)?;
+
+        let status = response.status();
+        log::debug!(
+            "GET {} {} in {}ms",
+            redact::url(&url.href),
+            status.as_u16(),
+            start.elapsed().as_millis()
+        );
+        if !status.is_success() {
+            let error = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "(Unknown)".to_owned());
+            return Err(Error::Cdn(status.as_u16(), error));
+        }
+
+        /* --quiet hides the progress bar along with the messages */
+        let bar = if self.options.on_progress.is_none() && log::log_enabled!(log::Level::Info) {
+            indicatif::ProgressBar::new(total).with_style(self.style.clone())
+        } else {
+            indicatif::ProgressBar::hidden()
+        };
+
+        /* Only finished files get their name, so nothing can mistake a partial one for a book */
+        let mut partial = path.clone().into_os_string();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        let mut file = File::create(&partial).await.map_err(Error::from_io)?;
+        cancel.working_on(Some(&partial));
+        let result = tokio::select! {
+            result = self.transfer(response, &mut file, isbn, total, &bar) => result,
+            _ = cancel.aborted() => Err(Error::Cancelled),
+        };
+        drop(file);
+
+        /* A stream can end cleanly and still be short */
+        let result = match result {
+            Ok(size) if total > 0 && size != total => Err(Error::Truncated(size, total)),
+            Ok(size) => {
+                /* Tag first, a crash while tagging mustn't leave a book under its name */
+                if let Some(book) = book {
+                    self.finish(client, &partial, &path, isbn, book, format)
+                        .await;
+                }
+                tokio::fs::rename(&partial, &path)
+                    .await
+                    .map(|_| size)
+                    .map_err(Error::from_io)
+            }
+            Err(err) => Err(err),
+        };
+        cancel.working_on(None);
+        let size = match result {
+            Ok(size) => size,
+            Err(err) => {
+                bar.abandon();
+                /* A truncated book is worse than none */
+                if let Err(err) = tokio::fs::remove_file(&partial).await {
+                    log::warn!("Failed to remove {}: {:?}", partial.display(), err);
+                }
+                return Err(err);
+            }
+        };
+
+        bar.finish_and_clear();
+        log::debug!("Wrote {} to {}", Size(size), path.display());
+
+        let download = hook::Download {
+            id: book.map(|book| book.id),
+            isbn: isbn.to_owned(),
+            title: book.map(|book| book.title.to_owned()),
+            author: book.map(|book| book.author.to_owned()),
+            series: book.and_then(|book| book.series.map(str::to_owned)),
+            part: book.and_then(|book| book.part),
+            path,
+            format,
+            size,
+        };
+
+        if let Some(hook) = &self.options.on_download {
+            if let Err(err) = hook.downloaded(&download).await {
+                log::warn!("Post-download hook failed: {:?}", err);
+            }
+        }
+
+        self.record(&download, book);
+        self.completed.lock().unwrap().push(download.clone());
+
+        Ok(download)
+    }
+
+    /* Stream the response into the file, returning the bytes written */
+    async fn transfer(
+        &self,
+        response: reqwest::Response,
+        file: &mut File,
+        isbn: &str,
+        total: u64,
+        bar: &indicatif::ProgressBar,
+    ) -> Result<u64> {
+        let mut size = 0;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let mut chunk = chunk.map_err(Error::from_reqwest)?;
+            /* Pausing would let the connection time out, so give the file up */
+            if let Some(window) = &self.options.window {
+                if !window.contains(chrono::Local::now().time()) {
+                    return Err(Error::WindowClosed);
+                }
+            }
+            size += chunk.len() as u64;
+            if let Some(limit) = &self.limit {
+                limit.take(chunk.len() as u64).await;
+            }
+            bar.inc(chunk.len() as u64);
+            if let Some(on_progress) = &self.options.on_progress {
+                on_progress(isbn, size, total);
+            }
+            file.write_all_buf(&mut chunk)
+                .await
+                .map_err(Error::from_io)?;
+        }
+
+        /* tokio writes in the background, make sure hooks and taggers see the whole file */
+        file.flush().await.map_err(Error::from_io)?;
+        Ok(size)
+    }
+
+    fn record(&self, download: &hook::Download, book: Option<&Metadata<'_>>) {
+        let details = book.and_then(|book| book.book);
+        let entry = library::Entry {
+            id: download.id,
+            isbn: download.isbn.clone(),
+            format: download.format,
+            title: download.title.clone(),
+            author: download.author.clone(),
+            narrator: details.map(|details| details.narrator.clone()),
+            series: download.series.clone(),
+            part: download.part,
+            path: download.path.clone(),
+            size: download.size,
+            downloaded: chrono::Utc::now(),
+            published: book.map(|book| book.published),
+            language: book.map(|book| book.language.to_owned()),
+            genres: details
+                .map(|details| {
+                    details
+                        .genres
+                        .iter()
+                        .map(|genre| genre.name.clone())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        let mut library = self.library.lock().unwrap();
+        library.insert(entry);
+        if let Err(err) = library.save() {
+            log::warn!("Failed to update library index: {:?}", err);
+        }
+    }
+
+    /// Run the batch hook over everything downloaded so far.
+    pub async fn finish_batch(&self) {
+        let Some(hook) = &self.options.on_finish else {
+            return;
+        };
+
+        let completed = std::mem::take(&mut *self.completed.lock().unwrap());
+        if let Err(err) = hook.finished(&completed).await {
+            log::warn!("Batch hook failed: {:?}", err);
+        }
+    }
+
+    /* Tag the partial file and decorate the book it becomes. Failures are reported, never fatal. */
+    async fn finish(
+        &self,
+        client: &Client,
+        partial: &Path,
+        path: &Path,
+        isbn: &str,
+        book: &Metadata<'_>,
+        format: BookFormat,
+    ) {
+        let cover = self.cover(book).await;
+
+        if format == BookFormat::AudioBook {
+            if let Err(err) = set_m4a_metadata(partial, book, cover.as_ref()) {
+                log::warn!("Failed to tag {}: {:?}", path.display(), err);
+            }
+        }
+
+        if let (true, Some(cover)) = (self.options.cover_sidecar, &cover) {
+            let sidecar = cover::sidecar_path(path, self.options.book_dirs);
+            if let Err(err) = cover::write_sidecar(&sidecar, cover).await {
+                log::warn!("Failed to write {}: {:?}", sidecar.display(), err);
+            }
+        }
+
+        if self.options.metadata_sidecar {
+            if let Err(err) = self.write_metadata(client, path, isbn, book).await {
+                log::warn!("Failed to write metadata for {}: {:?}", path.display(), err);
+            }
+        }
+    }
+
+    async fn write_metadata(
+        &self,
+        client: &Client,
+        path: &Path,
+        isbn: &str,
+        book: &Metadata<'_>,
+    ) -> Result<()> {
+        let fetched;
+        let details = match book.book {
+            Some(details) => details,
+            None => {
+                fetched = client.books(book_id(book.id)?).await?;
+                &fetched
+            }
+        };
+
+        let sidecar = Sidecar {
+            book: details,
+            isbn,
+            series: book.series,
+            part: book.part,
+        };
+
+        sidecar.write(path, self.options.book_dirs).await
+    }
+
+    async fn cover(&self, book: &Metadata<'_>) -> Option<cover::Cover> {
+        let Some(url) = book.cover else {
+            log::debug!("No image set for {}", book.title);
+            return None;
+        };
+
+        match cover::fetch(&self.client, url, self.options.cover_size).await {
+            Ok(cover) => Some(cover),
+            Err(err) => {
+                log::warn!("Failed to download cover: {:?}", err);
+                None
+            }
+        }
+    }
+}
+
+fn set_m4a_metadata(path: &Path, book: &Metadata<'_>, cover: Option<&cover::Cover>) -> Result<()> {
+    let mut tag = mp4ameta::Tag::read_from_path(path).map_err(Error::from_tag)?;
+
+    tag.set_title(book.title);
+    tag.set_year(book.published.year().to_string());
+    tag.set_album(book.title);
+    tag.set_artist(book.author);
+    tag.set_album_artist(book.author);
+
+    if let Some(cover) = cover {
+        tag.set_artwork(cover.to_mp4());
+    }
+
+    tag.write_to_path(path).map_err(Error::from_tag)
+}
@@ -0,0 +1,192 @@
+use std::str::FromStr;
+
+use crate::{
+    api::{Error, Result},
+    client::BookFormat,
+    library::{Entry, Library},
+};
+
+const CSV_HEADER: [&str; 15] = [
+    "title",
+    "author",
+    "narrator",
+    "series",
+    "part",
+    "isbn",
+    "id",
+    "format",
+    "path",
+    "size",
+    "downloaded",
+    "published",
+    "language",
+    "genres",
+    "other_isbn",
+];
+
+/* Column names understood by Calibre's CSV import */
+const CALIBRE_HEADER: [&str; 10] = [
+    "title",
+    "authors",
+    "series",
+    "series_index",
+    "identifiers",
+    "pubdate",
+    "languages",
+    "tags",
+    "formats",
+    "comments",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Calibre,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "calibre" => Ok(Self::Calibre),
+            _ => Err(format!("Unknown export format \"{s}\"")),
+        }
+    }
+}
+
+/// Render the library catalog. Paths are made absolute so the export stays
+/// usable outside of the library folder.
+pub fn export(library: &Library, format: ExportFormat) -> Result<String> {
+    let entries: Vec<Entry> = library
+        .entries()
+        .iter()
+        .cloned()
+        .map(|mut entry| {
+            entry.path = library.root().join(&entry.path);
+            entry
+        })
+        .collect();
+
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&entries).map_err(Error::from_serde),
+        ExportFormat::Csv => Ok(csv(&entries)),
+        ExportFormat::Calibre => Ok(calibre(&entries)),
+    }
+}
+
+fn csv(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    push_row(&mut out, CSV_HEADER.iter().map(|s| s.to_string()));
+
+    for entry in entries {
+        let format = match entry.format {
+            BookFormat::AudioBook => "audiobook",
+            BookFormat::EBook => "ebook",
+        };
+
+        push_row(
+            &mut out,
+            [
+                entry.title.clone().unwrap_or_default(),
+                entry.author.clone().unwrap_or_default(),
+                entry.narrator.clone().unwrap_or_default(),
+                entry.series.clone().unwrap_or_default(),
+                optional(entry.part),
+                entry.isbn.clone(),
+                optional(entry.id),
+                format.to_owned(),
+                entry.path.display().to_string(),
+                entry.size.to_string(),
+                entry.downloaded.to_rfc3339(),
+                optional(entry.published.map(|date| date.format("%Y-%m-%d"))),
+                entry.language.clone().unwrap_or_default(),
+                entry.genres.join(";"),
+                other_isbn(entries, entry).unwrap_or_default(),
+            ],
+        );
+    }
+
+    out
+}
+
+fn calibre(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    push_row(&mut out, CALIBRE_HEADER.iter().map(|s| s.to_string()));
+
+    for entry in entries {
+        let mut identifiers = vec![format!("isbn:{}", entry.isbn)];
+        if let Some(id) = entry.id {
+            identifiers.push(format!("bookbeat:{id}"));
+        }
+
+        let comments = entry
+            .narrator
+            .as_ref()
+            .map(|narrator| format!("Narrated by {narrator}"))
+            .unwrap_or_default();
+
+        push_row(
+            &mut out,
+            [
+                entry.title.clone().unwrap_or_default(),
+                /* Calibre separates multiple authors with & */
+                entry
+                    .author
+                    .as_deref()
+                    .unwrap_or_default()
+                    .replace(", ", " & "),
+                entry.series.clone().unwrap_or_default(),
+                optional(entry.part),
+                identifiers.join(","),
+                optional(entry.published.map(|date| date.format("%Y-%m-%d"))),
+                entry.language.clone().unwrap_or_default(),
+                entry.genres.join(","),
+                format_name(entry).to_owned(),
+                comments,
+            ],
+        );
+    }
+
+    out
+}
+
+/* Calibre lists formats by name, not by file */
+fn format_name(entry: &Entry) -> &'static str {
+    match entry.format {
+        BookFormat::AudioBook => "M4A",
+        BookFormat::EBook => "EPUB",
+    }
+}
+
+/* The matching ebook for an audiobook and vice versa */
+fn other_isbn(entries: &[Entry], entry: &Entry) -> Option<String> {
+    let id = entry.id?;
+    entries
+        .iter()
+        .find(|other| other.id == Some(id) && other.format != entry.format)
+        .map(|other| other.isbn.clone())
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn push_row(out: &mut String, fields: impl IntoIterator<Item = String>) {
+    for (index, field) in fields.into_iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(&field);
+        }
+    }
+    out.push_str("\r\n");
+}
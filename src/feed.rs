@@ -0,0 +1,178 @@
+use std::{fmt::Write, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+
+use crate::{
+    api::{Error, Result},
+    client::{DateTime, SearchBook},
+    xml::escape,
+};
+
+const BOOK_URL: &str = "https://www.bookbeat.com/book";
+/* Idle clients are dropped after this long */
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Entry {
+    pub id: usize,
+    pub title: String,
+    pub author: String,
+    pub image: Option<String>,
+    pub published: DateTime,
+    pub language: String,
+    pub grade: f32,
+    /// Which watchlist entry found the book
+    pub source: String,
+}
+
+impl Entry {
+    pub fn new(book: &SearchBook, source: String) -> Self {
+        Self {
+            id: book.id,
+            title: book.title.clone(),
+            author: book.author.clone(),
+            image: book.image.clone(),
+            published: book.published,
+            language: book.language.clone(),
+            grade: book.grade,
+            source,
+        }
+    }
+}
+
+/// Atom feed of new releases.
+pub struct Feed {
+    pub title: String,
+    entries: Vec<Entry>,
+}
+
+impl Feed {
+    pub fn new(title: String) -> Self {
+        Self {
+            title,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Books found by several watchlist entries are only listed once.
+    pub fn push(&mut self, entry: Entry) {
+        if !self.entries.iter().any(|existing| existing.id == entry.id) {
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_atom(&mut self) -> String {
+        self.entries
+            .sort_by_key(|entry| std::cmp::Reverse(entry.published));
+
+        let updated = self
+            .entries
+            .first()
+            .map(|entry| entry.published)
+            .unwrap_or_else(chrono::Utc::now);
+
+        let mut atom = String::new();
+        atom.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        atom.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        let _ = writeln!(atom, "  <title>{}</title>", escape(&self.title));
+        atom.push_str("  <id>urn:bookbeat:feed</id>\n");
+        let _ = writeln!(atom, "  <updated>{}</updated>", updated.to_rfc3339());
+        let _ = writeln!(atom, "  <link href=\"{BOOK_URL}\"/>");
+
+        for entry in &self.entries {
+            let link = format!("{}/{}", BOOK_URL, entry.id);
+
+            atom.push_str("  <entry>\n");
+            let _ = writeln!(atom, "    <title>{}</title>", escape(&entry.title));
+            let _ = writeln!(atom, "    <id>urn:bookbeat:book:{}</id>", entry.id);
+            let _ = writeln!(atom, "    <link href=\"{}\"/>", escape(&link));
+            let _ = writeln!(
+                atom,
+                "    <updated>{}</updated>",
+                entry.published.to_rfc3339()
+            );
+            let _ = writeln!(
+                atom,
+                "    <published>{}</published>",
+                entry.published.to_rfc3339()
+            );
+            let _ = writeln!(
+                atom,
+                "    <author><name>{}</name></author>",
+                escape(&entry.author)
+            );
+            let _ = writeln!(atom, "    <category term=\"{}\"/>", escape(&entry.language));
+
+            let mut content = String::new();
+            if let Some(image) = &entry.image {
+                let _ = write!(content, "<img src=\"{}\"/>", escape(image));
+            }
+            let _ = write!(
+                content,
+                "<p>{} by {}</p><p>Language: {}, Grade: {:.1}</p><p>Found via {}</p>",
+                escape(&entry.title),
+                escape(&entry.author),
+                escape(&entry.language),
+                entry.grade,
+                escape(&entry.source),
+            );
+            let _ = writeln!(
+                atom,
+                "    <content type=\"html\">{}</content>",
+                escape(&content)
+            );
+            atom.push_str("  </entry>\n");
+        }
+
+        atom.push_str("</feed>\n");
+        atom
+    }
+}
+
+/// Minimal HTTP server answering every request with the latest Atom feed,
+/// or 502 while there is none yet. Every connection gets its own task.
+pub async fn serve(listener: TcpListener, atom: watch::Receiver<Option<String>>) -> Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await.map_err(Error::from_io)?;
+        let atom = atom.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(socket, atom).await {
+                log::debug!("Feed request failed: {:?}", err);
+            }
+        });
+    }
+}
+
+async fn respond(
+    mut socket: TcpStream,
+    atom: watch::Receiver<Option<String>>,
+) -> std::io::Result<()> {
+    /* The request itself doesn't matter, but wait for it */
+    let mut request = [0u8; 1024];
+    match tokio::time::timeout(READ_TIMEOUT, socket.read(&mut request)).await {
+        Ok(result) => result?,
+        Err(_) => return Ok(()),
+    };
+
+    let response = match &*atom.borrow() {
+        Some(atom) => format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/atom+xml; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            atom.len(),
+            atom
+        ),
+        None => "HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            .to_owned(),
+    };
+    socket.write_all(response.as_bytes()).await
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::{
+    client::{Book, BookFormat, SearchBook},
+    locale::Language,
+};
+
+/// Narrows down the books of a selector before anything is licensed.
+/// Every condition left unset lets all books through.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub min_grade: Option<f32>,
+    /// Published on or after this day
+    pub since: Option<NaiveDate>,
+    /// Published on or before this day
+    pub until: Option<NaiveDate>,
+    /// Title has to match
+    pub include: Option<Regex>,
+    /// Title must not match
+    pub exclude: Option<Regex>,
+    /// Drop co-authored books and similar names when selecting an author
+    pub exact_author: bool,
+    /// Formats every book has to offer
+    pub formats: Vec<BookFormat>,
+    /// At most this many books per selector
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    /// `author` is the name an author selector searched for.
+    pub fn matches(&self, book: &SearchBook, author: Option<&str>) -> bool {
+        let published = book.published.date_naive();
+
+        self.min_grade.iter().all(|&grade| book.grade >= grade)
+            && self.since.iter().all(|&since| published >= since)
+            && self.until.iter().all(|&until| published <= until)
+            && self
+                .include
+                .iter()
+                .all(|include| include.is_match(&book.title))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|exclude| exclude.is_match(&book.title))
+            && (!self.exact_author
+                || author
+                    .iter()
+                    .all(|author| book.author.trim().eq_ignore_ascii_case(author.trim())))
+            && self.formats.iter().all(|format| match format {
+                BookFormat::AudioBook => book.audiobookisbn.is_some(),
+                BookFormat::EBook => book.ebookisbn.is_some(),
+            })
+    }
+
+    /// Keep the matching items in order, up to the limit.
+    pub fn apply<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        book: impl Fn(&T) -> &SearchBook,
+        author: Option<&str>,
+    ) -> Vec<T> {
+        items
+            .into_iter()
+            .filter(|item| self.matches(book(item), author))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Why the content settings left a book out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    Language,
+    Explicit,
+}
+
+/// Languages and explicit content allowed, also for the selectors the API
+/// doesn't filter, like series and ids.
+#[derive(Debug, Clone, Default)]
+pub struct Content {
+    /// Empty allows every language
+    pub languages: Vec<Language>,
+    /// Leave out explicit books, always set for kid accounts
+    pub sfw: bool,
+}
+
+impl Content {
+    /// Search hits and series parts have to be in one of the languages.
+    pub fn check(&self, book: &SearchBook) -> Option<Skip> {
+        if self.sfw && book.erotic == Some(true) {
+            return Some(Skip::Explicit);
+        }
+
+        let language = Language::named(&book.language);
+        if !self.languages.is_empty()
+            && !self
+                .languages
+                .iter()
+                .any(|allowed| allowed.matches(&language))
+        {
+            return Some(Skip::Language);
+        }
+
+        None
+    }
+
+    /// A book asked for by id or ISBN is only checked for explicit content.
+    pub fn check_book(&self, book: &Book) -> Option<Skip> {
+        (self.sfw && book.erotic == Some(true)).then_some(Skip::Explicit)
+    }
+
+    /// Explicit books can't be left out when the API didn't flag them either way.
+    pub fn unsure(&self, erotic: Option<bool>) -> bool {
+        self.sfw && erotic.is_none()
+    }
+
+    /// Leave out what the settings don't allow, telling how much that was.
+    pub fn allowed<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        book: impl Fn(&T) -> &SearchBook,
+    ) -> Vec<T> {
+        let mut skipped = Skipped::default();
+        let mut total = 0;
+        let kept: Vec<T> = items
+            .into_iter()
+            .filter(|item| {
+                total += 1;
+                match self.check(book(item)) {
+                    Some(skip) => {
+                        skipped.count(skip);
+                        false
+                    }
+                    None => true,
+                }
+            })
+            .collect();
+
+        if skipped.total() > 0 {
+            log::info!(
+                "Skipped {} of {} books: {}",
+                skipped.total(),
+                total,
+                skipped
+            );
+        }
+        let unsure = kept
+            .iter()
+            .filter(|item| self.unsure(book(item).erotic))
+            .count();
+        if unsure > 0 {
+            log::warn!("Kept {unsure} book(s) not flagged as explicit or not");
+        }
+        kept
+    }
+}
+
+/// Tally of the books left out by the content settings.
+#[derive(Debug, Default)]
+pub struct Skipped {
+    pub language: usize,
+    pub explicit: usize,
+}
+
+impl Skipped {
+    pub fn count(&mut self, skip: Skip) {
+        match skip {
+            Skip::Language => self.language += 1,
+            Skip::Explicit => self.explicit += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.language + self.explicit
+    }
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut reasons = Vec::new();
+        if self.language > 0 {
+            reasons.push(format!("{} in other languages", self.language));
+        }
+        if self.explicit > 0 {
+            reasons.push(format!("{} explicit", self.explicit));
+        }
+        f.write_str(&reasons.join(", "))
+    }
+}
@@ -0,0 +1,128 @@
+use std::{path::PathBuf, process::Stdio};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    api::{Error, Result},
+    client::BookFormat,
+};
+
+/// A finished download as seen by hooks.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Download {
+    pub id: Option<usize>,
+    pub isbn: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub part: Option<u32>,
+    pub path: PathBuf,
+    pub format: BookFormat,
+    pub size: u64,
+}
+
+impl Download {
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let format = match self.format {
+            BookFormat::AudioBook => "audiobook",
+            BookFormat::EBook => "ebook",
+        };
+
+        let mut env = vec![
+            ("BOOKBEAT_ISBN", self.isbn.clone()),
+            ("BOOKBEAT_PATH", self.path.display().to_string()),
+            ("BOOKBEAT_FORMAT", format.to_owned()),
+            ("BOOKBEAT_SIZE", self.size.to_string()),
+        ];
+
+        if let Some(id) = self.id {
+            env.push(("BOOKBEAT_ID", id.to_string()));
+        }
+        if let Some(title) = &self.title {
+            env.push(("BOOKBEAT_TITLE", title.clone()));
+        }
+        if let Some(author) = &self.author {
+            env.push(("BOOKBEAT_AUTHOR", author.clone()));
+        }
+        if let Some(series) = &self.series {
+            env.push(("BOOKBEAT_SERIES", series.clone()));
+        }
+        if let Some(part) = self.part {
+            env.push(("BOOKBEAT_PART", part.to_string()));
+        }
+
+        env
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Batch<'a> {
+    count: usize,
+    downloads: &'a [Download],
+}
+
+/// A shell command run after downloads. The event is passed as
+/// `BOOKBEAT_*` environment variables and as JSON on stdin.
+#[derive(Debug, Clone)]
+pub struct Hook {
+    command: String,
+}
+
+impl Hook {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+
+    pub async fn downloaded(&self, download: &Download) -> Result<()> {
+        self.run(download.env(), download).await
+    }
+
+    pub async fn finished(&self, downloads: &[Download]) -> Result<()> {
+        let env = vec![("BOOKBEAT_COUNT", downloads.len().to_string())];
+        let batch = Batch {
+            count: downloads.len(),
+            downloads,
+        };
+        self.run(env, &batch).await
+    }
+
+    async fn run<T: serde::Serialize>(
+        &self,
+        env: Vec<(&'static str, String)>,
+        payload: &T,
+    ) -> Result<()> {
+        let payload = serde_json::to_vec(payload).map_err(Error::from_serde)?;
+
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+
+        let mut child = command
+            .arg(&self.command)
+            .envs(env)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(Error::from_io)?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            /* The hook may not care about stdin and exit early */
+            let _ = stdin.write_all(&payload).await;
+        }
+
+        let status = child.wait().await.map_err(Error::from_io)?;
+        if !status.success() {
+            return Err(Error::Hook(format!(
+                "\"{}\" exited with {}",
+                self.command, status
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -1,2 +1,25 @@
 pub mod api;
+pub mod availability;
+pub mod cancel;
+pub mod cassette;
 pub mod client;
+pub mod cover;
+pub mod download;
+pub mod export;
+pub mod feed;
+pub mod filter;
+pub mod hook;
+pub mod library;
+pub mod locale;
+pub mod logger;
+pub mod plan;
+pub mod queue;
+pub mod redact;
+pub mod selection;
+pub mod sidecar;
+pub mod space;
+pub mod status;
+pub mod sync;
+pub mod throttle;
+pub mod watchlist;
+mod xml;
@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    api::{Error, Result},
+    client::{BookFormat, DateTime},
+};
+
+const LIBRARY_FILE: &str = "library.json";
+
+/// A file that was downloaded into the library.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct Entry {
+    pub id: Option<usize>,
+    pub isbn: String,
+    pub format: BookFormat,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub narrator: Option<String>,
+    pub series: Option<String>,
+    pub part: Option<u32>,
+    /// Relative to the library folder
+    pub path: PathBuf,
+    pub size: u64,
+    pub downloaded: DateTime,
+    pub published: Option<DateTime>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub genres: Vec<String>,
+}
+
+/// Index of everything downloaded into an output folder, stored as
+/// `library.json` next to the books.
+pub struct Library {
+    root: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Library {
+    pub fn load(root: &Path) -> Result<Self> {
+        let entries = match std::fs::read(root.join(LIBRARY_FILE)) {
+            Ok(data) => serde_json::from_slice(&data).map_err(Error::from_serde)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(Error::from_io(err)),
+        };
+
+        Ok(Self {
+            root: root.to_owned(),
+            entries,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let data = serde_json::to_vec_pretty(&self.entries).map_err(Error::from_serde)?;
+
+        /* Never leave a truncated index behind */
+        let path = self.root.join(LIBRARY_FILE);
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, data).map_err(Error::from_io)?;
+        std::fs::rename(&temp, &path).map_err(Error::from_io)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Average size of the files of this format, if there are any.
+    pub fn average_size(&self, format: BookFormat) -> Option<u64> {
+        let sizes: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.format == format)
+            .map(|entry| entry.size)
+            .collect();
+        if sizes.is_empty() {
+            return None;
+        }
+        Some(sizes.iter().sum::<u64>() / sizes.len() as u64)
+    }
+
+    pub fn contains(&self, isbn: &str) -> bool {
+        self.entries.iter().any(|entry| entry.isbn == isbn)
+    }
+
+    /// Add or replace the entry for the same ISBN.
+    pub fn insert(&mut self, mut entry: Entry) {
+        if let Ok(relative) = entry.path.strip_prefix(&self.root) {
+            entry.path = relative.to_owned();
+        }
+
+        self.entries.retain(|existing| existing.isbn != entry.isbn);
+        self.entries.push(entry);
+    }
+}
@@ -0,0 +1,203 @@
+use std::{fmt, str::FromStr};
+
+/* Name as used by the API, ISO code */
+const MARKETS: [(Market, &str, &str); 14] = [
+    (Market::Sweden, "Sweden", "se"),
+    (Market::Finland, "Finland", "fi"),
+    (Market::Norway, "Norway", "no"),
+    (Market::Denmark, "Denmark", "dk"),
+    (Market::Germany, "Germany", "de"),
+    (Market::Austria, "Austria", "at"),
+    (Market::Switzerland, "Switzerland", "ch"),
+    (Market::Netherlands, "Netherlands", "nl"),
+    (Market::Belgium, "Belgium", "be"),
+    (Market::Poland, "Poland", "pl"),
+    (Market::Spain, "Spain", "es"),
+    (Market::Italy, "Italy", "it"),
+    (Market::France, "France", "fr"),
+    (Market::UnitedKingdom, "UnitedKingdom", "gb"),
+];
+
+const LANGUAGES: [(Language, &str, &str); 12] = [
+    (Language::English, "English", "en"),
+    (Language::German, "German", "de"),
+    (Language::Swedish, "Swedish", "sv"),
+    (Language::Finnish, "Finnish", "fi"),
+    (Language::Norwegian, "Norwegian", "no"),
+    (Language::Danish, "Danish", "da"),
+    (Language::Dutch, "Dutch", "nl"),
+    (Language::Polish, "Polish", "pl"),
+    (Language::Spanish, "Spanish", "es"),
+    (Language::Italian, "Italian", "it"),
+    (Language::French, "French", "fr"),
+    (Language::Arabic, "Arabic", "ar"),
+];
+
+/// Storefront a request is made for, sent as `bb-market` and in book URLs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Market {
+    Sweden,
+    Finland,
+    Norway,
+    Denmark,
+    #[default]
+    Germany,
+    Austria,
+    Switzerland,
+    Netherlands,
+    Belgium,
+    Poland,
+    Spain,
+    Italy,
+    France,
+    UnitedKingdom,
+}
+
+/// Language of a book as used by the search filters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    German,
+    Swedish,
+    Finnish,
+    Norwegian,
+    Danish,
+    Dutch,
+    Polish,
+    Spanish,
+    Italian,
+    French,
+    Arabic,
+    /// Any other name, e.g. from a newer API or a watchlist
+    Other(String),
+}
+
+/* Match the API name or ISO code, ignoring case */
+fn parse<T: Clone>(table: &[(T, &str, &str)], s: &str, kind: &str) -> Result<T, String> {
+    table
+        .iter()
+        .find(|(_, name, code)| name.eq_ignore_ascii_case(s) || code.eq_ignore_ascii_case(s))
+        .map(|(value, _, _)| value.clone())
+        .ok_or_else(|| {
+            let names: Vec<&str> = table.iter().map(|(_, name, _)| *name).collect();
+            format!(
+                "Unknown {kind} \"{s}\", expected one of {}",
+                names.join(", ")
+            )
+        })
+}
+
+impl Market {
+    pub fn all() -> impl Iterator<Item = Self> {
+        MARKETS.iter().map(|(market, _, _)| *market)
+    }
+
+    pub fn name(&self) -> &'static str {
+        MARKETS
+            .iter()
+            .find(|(market, _, _)| market == self)
+            .unwrap()
+            .1
+    }
+
+    pub fn code(&self) -> &'static str {
+        MARKETS
+            .iter()
+            .find(|(market, _, _)| market == self)
+            .unwrap()
+            .2
+    }
+}
+
+impl Language {
+    pub fn all() -> impl Iterator<Item = Self> {
+        LANGUAGES.iter().map(|(language, _, _)| language.clone())
+    }
+
+    /// The known language by name or code, otherwise `Other`.
+    pub fn named(s: &str) -> Self {
+        parse(&LANGUAGES, s, "language").unwrap_or_else(|_| Self::Other(s.to_owned()))
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Other(name) => name,
+            _ => self.known().1,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Other(name) => name,
+            _ => self.known().2,
+        }
+    }
+
+    /// Same language, ignoring the case of other names.
+    pub fn matches(&self, other: &Self) -> bool {
+        self.name().eq_ignore_ascii_case(other.name())
+    }
+
+    fn known(&self) -> &(Language, &'static str, &'static str) {
+        LANGUAGES
+            .iter()
+            .find(|(language, _, _)| language == self)
+            .unwrap()
+    }
+}
+
+impl FromStr for Market {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(&MARKETS, s, "market")
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(&LANGUAGES, s, "language")
+    }
+}
+
+impl fmt::Display for Market {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/* Stored by name, so existing watchlists keep working */
+impl serde::Serialize for Language {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/* Never fail a whole file or response over one language */
+impl<'de> serde::Deserialize<'de> for Language {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Self::named(&s))
+    }
+}
+
+impl serde::Serialize for Market {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Market {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
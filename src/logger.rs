@@ -0,0 +1,98 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{mpsc::Sender, Mutex},
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::api::{Error, Result};
+
+/* Set while a full screen interface owns the terminal */
+static REDIRECT: Mutex<Option<Sender<String>>> = Mutex::new(None);
+
+/// Writes the records of this crate to stderr and optionally appends them
+/// to a file. Records of dependencies are dropped.
+pub struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+}
+
+impl Logger {
+    /// Install the logger. Later calls keep the first logger.
+    pub fn init(level: LevelFilter, file: Option<&Path>) -> Result<()> {
+        let file = match file {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(Error::from_io)?,
+            ),
+            None => None,
+        };
+
+        let logger = Self {
+            level,
+            file: file.map(Mutex::new),
+        };
+
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            log::set_max_level(level);
+        }
+
+        Ok(())
+    }
+
+    /// Send the messages meant for stderr to `sink` instead, or back to
+    /// stderr with `None`. The log file is unaffected.
+    pub fn redirect(sink: Option<Sender<String>>) {
+        *REDIRECT.lock().unwrap() = sink;
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with("bookbeat")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        /* Plain messages are the regular output of the command line */
+        let message = if record.level() == Level::Info {
+            record.args().to_string()
+        } else {
+            let level = record.level().as_str().to_lowercase();
+            format!("{}: {}", level, record.args())
+        };
+
+        match &*REDIRECT.lock().unwrap() {
+            Some(sink) => {
+                let _ = sink.send(message);
+            }
+            None => eprintln!("{message}"),
+        }
+
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap();
+            let _ = writeln!(
+                file,
+                "{} {:5} {} {}",
+                chrono::Utc::now().to_rfc3339(),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
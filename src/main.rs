@@ -1,35 +1,188 @@
-mod api;
-mod client;
-
-use std::{fs, path::PathBuf, str::FromStr};
-
-use chrono::Datelike;
-use futures_util::stream::StreamExt;
-use tokio::fs::File;
-use tokio::io::{stdin, AsyncReadExt, AsyncWriteExt};
-
-use crate::client::{AuthToken, Client, BookFormat};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::NaiveDate;
+use log::LevelFilter;
+use regex::Regex;
+use tokio::{
+    io::{stdin, AsyncReadExt},
+    net::TcpListener,
+};
+
+mod tui;
+
+use bookbeat::{
+    api,
+    availability::{self, Availability},
+    cancel::Cancel,
+    cassette::Cassette,
+    client::{self, AuthToken, BookFormat, Client, ClientConfig, Genres, SearchBook},
+    download::{self, summarize, Batch, Downloader},
+    export::{self, ExportFormat},
+    feed::{self, Feed},
+    filter::{Content, Filter},
+    hook::Hook,
+    library::Library,
+    locale::{Language, Market},
+    logger::Logger,
+    plan::{EditionPolicy, Plan, WorkItem},
+    queue::Queue,
+    space::Size,
+    sync::{self, Settings},
+    watchlist::{Target, Watch, Watchlist},
+};
 
 const TOKEN_PATH: &str = "token.json";
-const USAGE: &str = "Usage: bookbeat [OPTION]... --output [FOLDER]
+const WATCHLIST_PATH: &str = "watchlist.json";
+const DEFAULT_RESERVE: Size = Size(100 << 20);
+const USAGE: &str = "Usage: bookbeat [COMMAND] [OPTION]... --output [FOLDER]
+
+Commands:
+ (none)                 Download the selected books
+ sync                   Download new books of everything on the watchlist
+ watch add|remove       Follow or unfollow the given --author, --narrator or --series
+ watch list             Show the watchlist
+ queue run              Download the files left over when the licensing limit was reached
+ queue list             Show the queued files
+ queue remove [N|ISBN]  Drop files from the queue, by position or ISBN
+ queue reorder [N|ISBN] [POSITION]
+                        Move a queued file to another position
+ feed                   Atom feed of recent books on the watchlist
+ export                 Dump the library catalog of the output folder
+ status                 Show the BookBeat service status
+ info --isbn [ISBN]     Show the book an ISBN belongs to
+ markets                List the markets and their codes
+ availability [ID|ISBN] Compare the editions of a book across markets
+ genres                 List the genres of the market
+ languages              List the languages and their codes
+ tui                    Search, browse and queue downloads interactively
+
+Queue options:
+ --no-wait              Stop instead of waiting for the licensing limit to reset
+
+Feed options:
+ --days [DAYS]          Include books published in the last DAYS (Default: 30)
+ --file [PATH]          Write the feed to PATH instead of stdout
+ --serve [ADDRESS]      Serve the feed over HTTP, e.g. 127.0.0.1:8080
+
+Export options:
+ --format [FORMAT]      csv, json or calibre (Default: csv)
+ --file [PATH]          Write the export to PATH instead of stdout
+
+Filter options for --author, --narrator, --genre and --series:
+ --min-grade [GRADE]    Skip books rated lower, e.g. 4.0
+ --since [DATE]         Skip books published before DATE, e.g. 2020-01-31
+ --until [DATE]         Skip books published after DATE
+ --title [REGEX]        Only books whose title matches, e.g. (?i)harbor
+ --exclude-title [RE]   Skip books whose title matches
+ --exact-author         Skip co-authored books and similarly named authors
+ --has [FORMAT]         Only books offered as audiobook or ebook
+ --limit [COUNT]        At most COUNT books per selector
+
+Availability options:
+ --in [MARKET]          Only compare these markets (Default: all)
 
 Options:
  --username [NAME]      Username or E-Mail address
  --password [PASSWORD]  Password
  --force-fetch          Overwrite token cache
- --sfw                  Exclude explicit results
+ --sfw                  Exclude explicit books (Always on for kid accounts)
  --ebook [boolean]      Download ebooks (Default: false)
  --audiobook [boolean]  Download audio books (Default: true)
- --market [MARKET]      Target market (Default: Germany)
+ --market [MARKET]      Target market, name or code (Default: Germany)
+ --any-market           Get formats missing in the market of --id from another one
+ --edition [POLICY]     Editions of books with several of a format:
+                        newest, oldest, publisher:NAME, isbn:ISBN or all
+                        (Default: newest)
+ --dry-run              Show the files that would be downloaded and stop
+ --book-dirs            Store every book in its own Author/Title folder
+ --cover-size [PIXELS]  Shrink covers to fit and store them as JPEG
+ --cover-sidecar        Write the cover as JPEG next to each downloaded book
+ --metadata-sidecar     Write Audiobookshelf/Plex metadata next to each book
+ --on-download [CMD]    Run a command after every finished file
+ --on-finish [CMD]      Run a command after all downloads finished
+ --limit-rate [RATE]    Download at most RATE bytes per second, e.g. 500k or 2M
+ --window [HH:MM-HH:MM] Only download at this time of day, e.g. 22:00-06:00
+                        (A download running when it closes is queued again)
+ --reserve [SIZE]       Keep SIZE free on the output disk, e.g. 2G (Default: 100M)
+ --ignore-estimate      Only warn when the estimated sizes won't fit
+ --host [URL]           Send all requests to URL, e.g. staging or a local mock
+ --api-host [URL]       API host (Default: https://api.bookbeat.com)
+ --search-host [URL]    Search host (Default: https://search-api.bookbeat.com)
+ --status-host [URL]    Status page host (Default: https://status.bookbeat.com)
+ --status-policy [P]    Log in while BookBeat is degraded or down:
+                        strict, degraded or ignore (Default: degraded)
+ --record [DIR]         Save the API traffic to DIR, without credentials
+ --replay [DIR]         Answer API requests from a recording in DIR
+ --proxy [URL]          HTTP(S) or SOCKS5 proxy (Default: HTTPS_PROXY)
+ --ca-cert [PATH]       Trust the PEM certificate at PATH, e.g. of a tracing proxy
+ -v, -vv                Log requests, -vv also logs their bodies
+ --quiet                Only log warnings and errors
+ --log-file [PATH]      Append the log to PATH
 
 Variable count options:
  --id [ID]              Bookbeat ID
  --audioisbn [ISBN]     International Standard Book Number (Audiobook)
  --ebookisbn [ISBN]     International Standard Book Number (Ebook)
  --author [NAME]        Author Name
+ --narrator [NAME]      Narrator Name
+ --genre [ID|NAME]      Genre, see the genres command
  --series [ID]          Series ID
- --language [LANG]      Language Name (Default: English)";
-const PROGRESS_TEMPLATE: &str = "{wide_bar} [{bytes:10}/{total_bytes:10}] {eta:4}";
+ --language [LANG]      Language name or code (Default: English)";
+
+/* What to fetch for a selector */
+struct Preferences {
+    sfw: bool,
+    kid: bool,
+    audiobook: bool,
+    ebook: bool,
+    languages: Vec<Language>,
+}
+
+impl Preferences {
+    fn formats(&self) -> Vec<BookFormat> {
+        let mut formats = Vec::new();
+        if self.audiobook {
+            formats.push(BookFormat::AudioBook);
+        }
+        if self.ebook {
+            formats.push(BookFormat::EBook);
+        }
+        formats
+    }
+
+    /* Kid accounts never get explicit books, whatever the command line says */
+    fn includeerotic(&self) -> bool {
+        !self.sfw && !self.kid
+    }
+
+    fn content(&self) -> Content {
+        Content {
+            languages: self.languages.clone(),
+            sfw: !self.includeerotic(),
+        }
+    }
+
+    /* Watchlist entries may override the command line */
+    fn for_watch(&self, watch: &Watch) -> Self {
+        let languages = if watch.languages.is_empty() {
+            self.languages.clone()
+        } else {
+            watch.languages.clone()
+        };
+
+        Self {
+            sfw: self.sfw,
+            kid: self.kid,
+            audiobook: watch.audiobook.unwrap_or(self.audiobook),
+            ebook: watch.ebook.unwrap_or(self.ebook),
+            languages,
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> api::Result<()> {
@@ -40,11 +193,59 @@ async fn main() -> api::Result<()> {
         return Ok(());
     }
 
+    /* -v, -vv or -v -v */
+    let mut verbosity = 0;
+    while args.contains("-vv") {
+        verbosity += 2;
+    }
+    while args.contains("-v") {
+        verbosity += 1;
+    }
+    let level = match (args.contains("--quiet"), verbosity) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let log_file: Option<PathBuf> = args.opt_value_from_str("--log-file").unwrap();
+    Logger::init(level, log_file.as_deref())?;
+
+    let command = args.subcommand().unwrap();
+    match command.as_deref() {
+        None | Some("sync") | Some("feed") | Some("info") | Some("availability")
+        | Some("genres") | Some("tui") => {}
+        Some("watch") => return watch(args),
+        Some("queue") => {
+            let action = args.subcommand().unwrap();
+            if action.as_deref() != Some("run") {
+                return queue(args, action.as_deref());
+            }
+        }
+        Some("export") => return export(args),
+        Some("status") => return status(args).await,
+        Some("markets") => {
+            for market in Market::all() {
+                println!("{:14} {}", market.name(), market.code());
+            }
+            return Ok(());
+        }
+        Some("languages") => {
+            for language in Language::all() {
+                println!("{:14} {}", language.name(), language.code());
+            }
+            return Ok(());
+        }
+        Some(command) => {
+            eprintln!("Unknown command \"{command}\"\n\n{USAGE}");
+            return Ok(());
+        }
+    }
+
     /* Load ouput directory, fallback to cwd */
     let dest = if let Ok(path) = args.value_from_str::<&str, String>("--output") {
         PathBuf::from_str(&path).unwrap()
     } else {
-        eprintln!("Storing in current working directory");
+        log::info!("Storing in current working directory");
         std::env::current_dir().unwrap()
     };
 
@@ -56,19 +257,53 @@ async fn main() -> api::Result<()> {
     let sfw = args.contains("--sfw");
     let ebook = args.value_from_str("--ebook").unwrap_or(false);
     let audiobook = args.value_from_str("--audiobook").unwrap_or(true);
-    let market = args
-        .value_from_str("--market")
-        .unwrap_or_else(|_| "Germany".to_owned());
+    let config = client_config(&mut args);
+
+    let options = download::Options {
+        book_dirs: args.contains("--book-dirs"),
+        cover_size: args.opt_value_from_str("--cover-size").unwrap(),
+        cover_sidecar: args.contains("--cover-sidecar"),
+        metadata_sidecar: args.contains("--metadata-sidecar"),
+        on_download: args
+            .opt_value_from_str("--on-download")
+            .unwrap()
+            .map(Hook::new),
+        on_finish: args
+            .opt_value_from_str("--on-finish")
+            .unwrap()
+            .map(Hook::new),
+        on_progress: None,
+        transport: config.transport.clone(),
+        limit_rate: valid(args.opt_value_from_str("--limit-rate")),
+        window: valid(args.opt_value_from_str("--window")),
+        reserve: valid(args.opt_value_from_str("--reserve"))
+            .unwrap_or(DEFAULT_RESERVE)
+            .0,
+        ignore_estimate: args.contains("--ignore-estimate"),
+        cancel: Cancel::default(),
+    };
 
-    let mut languages: Vec<String> = args.values_from_str("--language").unwrap();
+    let mut languages: Vec<Language> = valid(args.values_from_str("--language"));
     if languages.is_empty() {
-        languages.push("English".to_owned());
+        languages.push(Language::English);
     }
-    let languages: Vec<&str> = languages.iter().map(|s| s.as_str()).collect();
+
+    /* Parsed before logging in, so typos fail fast */
+    let filter = filter_options(&mut args);
+    let policy: EditionPolicy = valid(args.opt_value_from_str("--edition")).unwrap_or_default();
+    let dry_run = args.contains("--dry-run");
+
+    let mut preferences = Preferences {
+        sfw,
+        kid: false,
+        audiobook,
+        ebook,
+        languages,
+    };
 
     let client = if let Ok(token) = fs::read_to_string(TOKEN_PATH) {
         let token: AuthToken = serde_json::from_str(&token).unwrap();
-        let client = Client::from_token(token).await?;
+        let client = Client::from_token_with(config, token).await?;
 
         write_token(client.extract_token()).await;
 
@@ -77,7 +312,7 @@ async fn main() -> api::Result<()> {
         let username: String = args.value_from_str("--username").unwrap();
         let password: String = args.value_from_str("--password").unwrap();
 
-        let client = Client::login(&username, &password).await?;
+        let client = Client::login_with(config, &username, &password).await?;
 
         write_token(client.extract_token()).await;
 
@@ -85,288 +320,885 @@ async fn main() -> api::Result<()> {
     };
 
     let user = client.users().await?;
+    preferences.kid = user.iskid;
 
     if !user.subscribed() {
-        eprintln!("WARNING: Not subscribed. There will be dragons.");
+        log::warn!("Not subscribed. There will be dragons.");
         if !confirm("Continue?").await {
             return Ok(());
         }
     }
 
-    let downloader = Downloader::new(dest);
+    if command.as_deref() == Some("info") {
+        return info(&client, args).await;
+    }
 
-    while let Ok(Some(id)) = args.opt_value_from_str::<&str, u32>("--id") {
-        let book = client.books(&market, id).await?;
-
-        for edition in book.editions {
-            let isbn = &edition.isbn;
-            match edition.format {
-                BookFormat::AudioBook => if audiobook {
-                    let file_name = format!("{} ({}).m4a", &book.title, isbn);
-    
-                    let _ = downloader.download(&client, isbn, &file_name).await?;
-                }
-                BookFormat::EBook => if ebook {
-                    let file_name = format!("{} ({}).epub", &book.title, isbn);
-    
-                    let _ = downloader.download(&client, isbn, &file_name).await?;
-                }
-            }
+    if command.as_deref() == Some("availability") {
+        return availability(&client, args).await;
+    }
+
+    if command.as_deref() == Some("genres") {
+        for genre in client.genres().await? {
+            println!("{:6} {}", genre.genreid, genre.name);
         }
+        return Ok(());
+    }
+
+    if command.as_deref() == Some("feed") {
+        let days = args.opt_value_from_str("--days").unwrap().unwrap_or(30);
+        if let Some(address) = args.opt_value_from_str::<&str, String>("--serve").unwrap() {
+            return serve_feed(&client, &preferences, days, &address).await;
+        }
+
+        let atom = build_feed(&client, &preferences, days).await?.to_atom();
+        match args.opt_value_from_str::<&str, PathBuf>("--file").unwrap() {
+            Some(path) => fs::write(path, atom).map_err(api::Error::from_io)?,
+            None => print!("{atom}"),
+        }
+        return Ok(());
+    }
+
+    if command.as_deref() == Some("tui") {
+        return tui::run(client, dest, options, &preferences).await;
+    }
+
+    let downloader = Downloader::new(dest.clone(), options)?;
+
+    /* Ctrl-C is only taken over once downloads start, until then it exits as usual */
+    if command.as_deref() == Some("queue") {
+        let wait = !args.contains("--no-wait");
+        downloader.cancel().on_ctrl_c();
+        let result = run_queue(client, &downloader, &dest, wait).await;
+        downloader.finish_batch().await;
+        return result;
+    }
+
+    if command.as_deref() == Some("sync") {
+        downloader.cancel().on_ctrl_c();
+        let result = sync(&client, &downloader, &preferences).await;
+        downloader.finish_batch().await;
+        return result;
+    }
+
+    let any_market = args.contains("--any-market");
+    let formats = preferences.formats();
+    let content = preferences.content();
+    let market = client.market();
+
+    /* Resolve every selector first, so books found twice are only licensed once */
+    let mut plan = Plan::new(policy);
+    while let Ok(Some(id)) = args.opt_value_from_str::<&str, u32>("--id") {
+        plan_id(&client, &mut plan, id, &formats, &content, any_market).await?;
     }
 
     while let Ok(Some(name)) = args.opt_value_from_str::<&str, String>("--author") {
-        println!("Downloading author \"{}\"", name);
-
-        download_search(
-            &client,
-            Some(&name),
-            None,
-            sfw,
-            audiobook,
-            ebook,
-            &languages,
-            &downloader,
-        )
-        .await?;
+        log::info!("Looking up author \"{}\"", name);
+
+        let books = client
+            .search_all(
+                Some(&name),
+                None,
+                &preferences.languages,
+                preferences.includeerotic(),
+            )
+            .await?;
+        let books = content.allowed(books, |book| book);
+        for book in filtered(&filter, books, |book| book, Some(&name)) {
+            plan.add_search(&book, &formats, market);
+        }
     }
     while let Ok(Some(name)) = args.opt_value_from_str::<&str, String>("--narrator") {
-        println!("Downloading narrator \"{}\"", name);
-
-        download_search(
-            &client,
-            None,
-            Some(&name),
-            sfw,
-            audiobook,
-            ebook,
-            &languages,
-            &downloader,
-        )
-        .await?;
+        log::info!("Looking up narrator \"{}\"", name);
+
+        let books = client
+            .search_all(
+                None,
+                Some(&name),
+                &preferences.languages,
+                preferences.includeerotic(),
+            )
+            .await?;
+        let books = content.allowed(books, |book| book);
+        for book in filtered(&filter, books, |book| book, None) {
+            plan.add_search(&book, &formats, market);
+        }
+    }
+    let genre_names: Vec<String> = valid(args.values_from_str("--genre"));
+    let genres = if genre_names.is_empty() {
+        Vec::new()
+    } else {
+        client.genres().await?
+    };
+    for name in genre_names {
+        let Some(genre) = find_genre(&genres, &name) else {
+            log::warn!("Unknown genre \"{name}\", see \"bookbeat genres\"");
+            continue;
+        };
+        log::info!("Looking up genre \"{}\"", genre.name);
+
+        let books = client
+            .genre_books_all(
+                genre.genreid,
+                &preferences.languages,
+                preferences.includeerotic(),
+            )
+            .await?;
+        let books = content.allowed(books, |book| book);
+        for book in filtered(&filter, books, |book| book, None) {
+            plan.add_search(&book, &formats, market);
+        }
     }
     while let Ok(Some(id)) = args.opt_value_from_str::<&str, u32>("--series") {
-        let mut offset: usize = 0;
-        const STEP: usize = 50;
-        loop {
-            let series = client.series(id, offset, STEP).await?;
+        let series = client.series_all(id).await?;
 
-            println!(
-                "Downloading \"{}\" ({}/{})",
-                series.name, offset, series.count
-            );
+        log::info!("Looking up \"{}\" ({})", series.name, series.count);
 
-            let parts = series._embedded.parts;
+        let parts = content.allowed(&series._embedded.parts, |part| &part._embedded.book);
+        let parts = filtered(&filter, parts, |part| &part._embedded.book, None);
+        for part in parts {
+            plan.add_part(&series, part, &formats, market);
+        }
+    }
 
-            for part in &parts {
-                let prefix: Option<String> = part.partnumber.map(|index| format!("{:03} ", &index));
-                let prefix = prefix.as_deref().unwrap_or("");
-                let book = &part._embedded.book;
+    while let Ok(Some(isbn)) = args.opt_value_from_str::<&str, String>("--audioisbn") {
+        plan_isbn(&client, &mut plan, &isbn, BookFormat::AudioBook, &content).await;
+    }
+    while let Ok(Some(isbn)) = args.opt_value_from_str::<&str, String>("--ebookisbn") {
+        plan_isbn(&client, &mut plan, &isbn, BookFormat::EBook, &content).await;
+    }
 
-                if audiobook {
-                    if let Some(isbn) = &book.audiobookisbn {
-                        let file_name = format!("{}{} ({}).m4a", prefix, &book.title, isbn);
+    /* Listings name any edition, so look their books up for the policy to pick */
+    for id in plan.unresolved() {
+        match client.books(client::book_id(id)?).await {
+            Ok(book) => plan.add_details(&book),
+            Err(err) => log::warn!("Failed to look up the editions of book {id}: {err:?}"),
+        }
+    }
 
-                        let path = downloader.download(&client, isbn, &file_name).await?;
+    let formats: Vec<BookFormat> = plan.items().iter().map(|item| item.format).collect();
+    if dry_run {
+        print_plan(&plan, market);
+        let estimate: u64 = formats
+            .iter()
+            .map(|&format| downloader.estimate(format))
+            .sum();
+        println!("About {} needed", Size(estimate));
+        return Ok(());
+    }
 
-                        set_m4a_metadata(&path, book).await;
-                    }
-                }
+    downloader.check_space(&formats)?;
 
-                if ebook {
-                    if let Some(isbn) = &book.ebookisbn {
-                        let file_name = format!("{}{} ({}).epub", prefix, &book.title, isbn);
+    if plan.duplicates() > 0 {
+        log::info!(
+            "Skipping {} file(s) selected more than once",
+            plan.duplicates()
+        );
+    }
+    if plan.len() > 1 {
+        log::info!("Downloading {} file(s)", plan.len());
+    }
+    for item in plan.items() {
+        if let Some(note) = edition_note(item, plan.policy()) {
+            log::info!("{}: {}", item.file_name(), note);
+        }
+    }
 
-                        let _ = downloader.download(&client, isbn, &file_name).await?;
-                    }
-                }
-            }
+    downloader.cancel().on_ctrl_c();
 
-            if parts.len() < STEP {
-                break;
-            }
+    /* The batch hook also gets to see a batch that failed halfway */
+    let mut batch = Batch::default();
+    let result = match downloader
+        .download_all(&client, plan.items(), &mut batch)
+        .await
+    {
+        Ok(()) => downloader.settle(&client, &batch),
+        Err(err) => Err(err),
+    };
 
-            offset += STEP;
-        }
+    downloader.finish_batch().await;
+
+    result
+}
+
+/* Exit with the parse error instead of a panic, e.g. for a misspelled market */
+fn valid<T>(value: Result<T, pico_args::Error>) -> T {
+    value.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(2);
+    })
+}
+
+/* Conditions for the books of --author, --narrator, --genre and --series */
+fn filter_options(args: &mut pico_args::Arguments) -> Filter {
+    let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d");
+
+    Filter {
+        min_grade: valid(args.opt_value_from_str("--min-grade")),
+        since: valid(args.opt_value_from_fn("--since", date)),
+        until: valid(args.opt_value_from_fn("--until", date)),
+        include: valid(args.opt_value_from_fn("--title", Regex::new)),
+        exclude: valid(args.opt_value_from_fn("--exclude-title", Regex::new)),
+        exact_author: args.contains("--exact-author"),
+        formats: valid(args.values_from_str("--has")),
+        limit: valid(args.opt_value_from_str("--limit")),
     }
+}
 
-    while let Ok(Some(isbn)) = args.opt_value_from_str::<&str, String>("--audioisbn") {
-        let file_name = format!("{isbn}.m4a");
-        downloader.download(&client, &isbn, &file_name).await?;
+/* Apply the filter, telling how many books it dropped */
+fn filtered<T>(
+    filter: &Filter,
+    items: impl IntoIterator<Item = T>,
+    book: impl Fn(&T) -> &SearchBook,
+    author: Option<&str>,
+) -> Vec<T> {
+    let items: Vec<T> = items.into_iter().collect();
+    let total = items.len();
+    let kept = filter.apply(items, book, author);
+    if kept.len() < total {
+        log::info!("{} of {} books match the filters", kept.len(), total);
     }
-    while let Ok(Some(isbn)) = args.opt_value_from_str::<&str, String>("--ebookisbn") {
-        let file_name = format!("{isbn}.epub");
-        downloader.download(&client, &isbn, &file_name).await?;
+    kept
+}
+
+/* E.g. "newest of 2 editions: Abridged House (2021-04-01)" for books with several */
+fn edition_note(item: &WorkItem, policy: &EditionPolicy) -> Option<String> {
+    let editions = item.editions();
+    let edition = editions.iter().find(|edition| edition.isbn == item.isbn)?;
+    if editions.len() < 2 {
+        return None;
     }
 
-    Ok(())
+    let picked = policy
+        .pick(&editions)
+        .iter()
+        .any(|picked| picked.isbn == item.isbn);
+    let reason = if picked {
+        policy.to_string()
+    } else {
+        "chosen by ISBN".to_owned()
+    };
+    Some(format!(
+        "{} of {} editions: {} ({})",
+        reason,
+        editions.len(),
+        edition.publisher,
+        edition.published.format("%Y-%m-%d")
+    ))
 }
 
-async fn download_search(
+/* Hosts from the environment, overridden by the command line, market, status policy, proxy and cassette */
+fn client_config(args: &mut pico_args::Arguments) -> ClientConfig {
+    let mut config = ClientConfig::from_env();
+
+    if let Some(host) = args.opt_value_from_str::<&str, String>("--host").unwrap() {
+        config = ClientConfig::with_host(&host);
+    }
+    if let Some(host) = args.opt_value_from_str("--api-host").unwrap() {
+        config.api_host = host;
+    }
+    if let Some(host) = args.opt_value_from_str("--search-host").unwrap() {
+        config.search_host = host;
+    }
+    if let Some(host) = args.opt_value_from_str("--status-host").unwrap() {
+        config.status_host = host;
+    }
+
+    if let Some(market) = valid(args.opt_value_from_str("--market")) {
+        config.market = market;
+    }
+
+    if let Some(policy) = valid(args.opt_value_from_str("--status-policy")) {
+        config.status_policy = policy;
+    }
+
+    config.transport.proxy = args.opt_value_from_str("--proxy").unwrap();
+    config.transport.ca_cert = args.opt_value_from_str("--ca-cert").unwrap();
+
+    if let Some(dir) = args.opt_value_from_str("--record").unwrap() {
+        config.cassette = Some(Cassette::Record(dir));
+    } else if let Some(dir) = args.opt_value_from_str("--replay").unwrap() {
+        config.cassette = Some(Cassette::Replay(dir));
+    }
+
+    config
+}
+
+/* Plan the enabled formats of a book, from another market if --any-market allows it */
+async fn plan_id(
     client: &Client,
-    author: Option<&str>,
-    narrator: Option<&str>,
-    sfw: bool,
-    audiobook: bool,
-    ebook: bool,
-    languages: &[&str],
-    downloader: &Downloader,
+    plan: &mut Plan,
+    id: u32,
+    formats: &[BookFormat],
+    content: &Content,
+    any_market: bool,
 ) -> api::Result<()> {
-    let mut offset: usize = 0;
-    const STEP: usize = 50;
-    loop {
-        let search = client
-            .search(author, narrator, offset, STEP, languages, !sfw)
-            .await?;
-
-        let books = search._embedded.books;
+    let mut found = match client.books(id).await {
+        Ok(book) => vec![Availability {
+            market: client.market(),
+            book: Some(book),
+        }],
+        Err(api::Error::Api(404, _)) if any_market => Vec::new(),
+        Err(err) => return Err(err),
+    };
 
-        for book in &books {
-            if audiobook {
-                if let Some(isbn) = &book.audiobookisbn {
-                    let file_name = format!("{} ({}).m4a", &book.title, isbn);
+    let missing = formats
+        .iter()
+        .any(|&format| availability::pick(&found, format).is_none());
+    if any_market && missing {
+        let others: Vec<Market> = availability::markets_from(client.market())
+            .into_iter()
+            .skip(1)
+            .collect();
+        found.extend(availability::compare(client, id, &others).await?);
+    }
 
-                    let path = downloader.download(client, isbn, &file_name).await?;
+    let explicit = found
+        .iter()
+        .filter_map(|entry| entry.book.as_ref())
+        .find(|book| content.check_book(book).is_some());
+    if let Some(book) = explicit {
+        log::warn!("Skipping \"{}\" ({id}), it is explicit", book.title);
+        return Ok(());
+    }
+    let unsure = found
+        .iter()
+        .filter_map(|entry| entry.book.as_ref())
+        .find(|book| content.unsure(book.erotic));
+    if let Some(book) = unsure {
+        log::warn!(
+            "Keeping \"{}\" ({id}), it isn't flagged as explicit or not",
+            book.title
+        );
+    }
 
-                    set_m4a_metadata(&path, book).await;
-                }
+    for &format in formats {
+        let Some(entry) = availability::pick(&found, format) else {
+            if any_market {
+                log::warn!("No market offers book {id} as {}", format.extension());
             }
+            continue;
+        };
+
+        plan.add_book(entry.book.as_ref().unwrap(), format, entry.market);
+    }
 
-            if ebook {
-                if let Some(isbn) = &book.ebookisbn {
-                    let file_name = format!("{} ({}).epub", &book.title, isbn);
+    Ok(())
+}
 
-                    let _ = downloader.download(client, isbn, &file_name).await?;
-                }
-            }
+/* What would be downloaded, with the edition picked of several */
+fn print_plan(plan: &Plan, market: Market) {
+    for item in plan.items() {
+        print!(
+            "{} {:4} {}",
+            item.isbn,
+            item.format.extension(),
+            item.file_name()
+        );
+        if item.market != market {
+            print!(" (from {})", item.market);
+        }
+        println!();
+
+        if let Some(note) = edition_note(item, plan.policy()) {
+            println!("    {note}");
+        }
+    }
+
+    println!(
+        "{} file(s), {} selected more than once",
+        plan.len(),
+        plan.duplicates()
+    );
+}
+
+/* License in the market the item was found in */
+/* Genres are given by id or by name, ignoring case */
+fn find_genre<'a>(genres: &'a [Genres], genre: &str) -> Option<&'a Genres> {
+    let id: Option<u32> = genre.parse().ok();
+    genres
+        .iter()
+        .find(|known| Some(known.genreid) == id || known.name.eq_ignore_ascii_case(genre))
+}
+
+/* Resolve a bare ISBN so it is named and tagged like an --id download */
+async fn plan_isbn(
+    client: &Client,
+    plan: &mut Plan,
+    isbn: &str,
+    format: BookFormat,
+    content: &Content,
+) {
+    let book = match client.book_by_isbn(isbn).await {
+        Ok(Some(book)) => Some(book),
+        Ok(None) => {
+            log::warn!("No book found for ISBN {isbn}, downloading without metadata");
+            None
+        }
+        Err(err) => {
+            log::warn!("Failed to look up ISBN {isbn}, downloading without metadata: {err:?}");
+            None
         }
+    };
+
+    if let Some(book) = book
+        .as_ref()
+        .filter(|book| content.check_book(book).is_some())
+    {
+        log::warn!("Skipping \"{}\" ({isbn}), it is explicit", book.title);
+        return;
+    }
+    if let Some(book) = book.as_ref().filter(|book| content.unsure(book.erotic)) {
+        log::warn!(
+            "Keeping \"{}\" ({isbn}), it isn't flagged as explicit or not",
+            book.title
+        );
+    }
+
+    plan.push(WorkItem {
+        isbn: isbn.to_owned(),
+        format,
+        market: client.market(),
+        search: None,
+        book,
+        series: None,
+        part: None,
+        pinned: true,
+    });
+}
 
-        if books.len() < STEP {
-            break;
+async fn availability(client: &Client, mut args: pico_args::Arguments) -> api::Result<()> {
+    let mut markets: Vec<Market> = valid(args.values_from_str("--in"));
+    if markets.is_empty() {
+        markets = availability::markets_from(client.market());
+    }
+
+    /* Book ids are short, ISBNs have 10 or 13 digits */
+    let target: String = valid(args.free_from_str());
+    let id = if target.len() >= 10 {
+        match client.book_by_isbn(&target).await? {
+            Some(book) => client::book_id(book.id)?,
+            None => {
+                log::warn!("No book found for ISBN {target}");
+                return Ok(());
+            }
         }
+    } else {
+        target.parse().unwrap_or_else(|_| {
+            eprintln!("\"{target}\" is neither a book id nor an ISBN");
+            std::process::exit(2);
+        })
+    };
 
-        offset += STEP;
+    let found = availability::compare(client, id, &markets).await?;
+    match found.iter().find_map(|entry| entry.book.as_ref()) {
+        Some(book) => println!("{} by {} ({})", book.title, book.author, book.id),
+        None => println!("Book {id} isn't available in any of the markets"),
+    }
+
+    for entry in &found {
+        let Some(book) = &entry.book else {
+            println!("  {:14} not available", entry.market.name());
+            continue;
+        };
+
+        for (index, edition) in book.editions.iter().enumerate() {
+            let market = if index == 0 { entry.market.name() } else { "" };
+            let format = match edition.format {
+                BookFormat::AudioBook => "audiobook",
+                BookFormat::EBook => "ebook",
+            };
+            println!(
+                "  {:14} {:9} {} {} ({})",
+                market,
+                format,
+                edition.isbn,
+                edition.publisher,
+                edition.published.format("%Y-%m-%d")
+            );
+        }
     }
 
     Ok(())
 }
 
-async fn confirm(message: &str) -> bool {
-    eprintln!("{} [y/N]", message);
-    let answer = stdin().read_u8().await.expect("Aborted");
-    matches!(answer, b'y' | b'Y')
+async fn info(client: &Client, mut args: pico_args::Arguments) -> api::Result<()> {
+    while let Some(isbn) = args.opt_value_from_str::<&str, String>("--isbn").unwrap() {
+        let Some(book) = client.book_by_isbn(&isbn).await? else {
+            log::warn!("No book found for ISBN {isbn}");
+            continue;
+        };
+
+        println!("{} ({})", book.title, book.id);
+        println!("  Author:    {}", book.author);
+        println!("  Narrator:  {}", book.narrator);
+        println!("  Language:  {}", book.language);
+        println!("  Published: {}", book.published.format("%Y-%m-%d"));
+        println!("  Grade:     {:.1}", book.grade);
+        let genres: Vec<&str> = book
+            .genres
+            .iter()
+            .map(|genre| genre.name.as_str())
+            .collect();
+        println!("  Genres:    {}", genres.join(", "));
+        println!("  Editions:");
+        for edition in &book.editions {
+            let format = match edition.format {
+                BookFormat::AudioBook => "audiobook",
+                BookFormat::EBook => "ebook",
+            };
+            let marker = if edition.isbn == isbn { "*" } else { " " };
+            println!(
+                "   {} {} {:9} {} ({})",
+                marker,
+                edition.isbn,
+                format,
+                edition.publisher,
+                edition.published.format("%Y-%m-%d")
+            );
+        }
+        println!("\n{}\n", book.summary);
+    }
+
+    Ok(())
 }
 
-async fn write_token(token: &client::AuthToken) {
-    let token = serde_json::to_vec(token).unwrap();
-    std::fs::write(TOKEN_PATH, &token).unwrap();
+/* Download the enabled formats of a book, optionally skipping files already in the library */
+async fn status(mut args: pico_args::Arguments) -> api::Result<()> {
+    let config = client_config(&mut args);
+    let status = Client::service_status(&config).await?;
+    print!("{status}");
+    Ok(())
 }
 
-struct Downloader {
-    path: PathBuf,
-    client: reqwest::Client,
-    style: indicatif::ProgressStyle,
+async fn sync(
+    client: &Client,
+    downloader: &Downloader,
+    preferences: &Preferences,
+) -> api::Result<()> {
+    let mut watchlist = Watchlist::load(WATCHLIST_PATH.as_ref())?;
+    if watchlist.entries.is_empty() {
+        log::warn!("Watchlist is empty, add entries with \"bookbeat watch add\"");
+        return Ok(());
+    }
+
+    let batch = sync::run(client, downloader, &mut watchlist, |watch| {
+        let preferences = preferences.for_watch(watch);
+        Settings {
+            formats: preferences.formats(),
+            content: preferences.content(),
+        }
+    })
+    .await?;
+    downloader.settle(client, &batch)?;
+    if batch.stopped.is_some() {
+        log::info!("The next sync picks up the rest");
+    }
+
+    Ok(())
 }
 
-impl Downloader {
-    fn new(path: PathBuf) -> Self {
-        /* 15 Minute keepalive */
-        let keepalive = std::time::Duration::from_secs(15 * 60);
+async fn build_feed(client: &Client, preferences: &Preferences, days: i64) -> api::Result<Feed> {
+    let watchlist = Watchlist::load(WATCHLIST_PATH.as_ref())?;
+    let since = chrono::Utc::now() - chrono::Duration::days(days);
 
-        let client = reqwest::ClientBuilder::new()
-            .user_agent("okhttp/4.10.0")
-            .tcp_keepalive(keepalive)
-            .build()
-            .unwrap();
+    let mut feed = Feed::new("BookBeat new releases".to_owned());
+    for watch in &watchlist.entries {
+        let preferences = preferences.for_watch(watch);
+        let languages = &preferences.languages;
 
-        let style = indicatif::ProgressStyle::default_bar()
-            .template(PROGRESS_TEMPLATE)
-            .unwrap();
+        let books = match &watch.target {
+            Target::Author(name) => {
+                client
+                    .search_all(Some(name), None, languages, preferences.includeerotic())
+                    .await?
+            }
+            Target::Narrator(name) => {
+                client
+                    .search_all(None, Some(name), languages, preferences.includeerotic())
+                    .await?
+            }
+            Target::Series(id) => {
+                let series = client.series_all(*id).await?;
+                series
+                    ._embedded
+                    .parts
+                    .into_iter()
+                    .map(|part| part._embedded.book)
+                    .collect()
+            }
+        };
 
-        Self {
-            path,
-            client,
-            style,
+        let books = preferences.content().allowed(books, |book| book);
+        for book in books.iter().filter(|book| book.published >= since) {
+            feed.push(feed::Entry::new(book, watch.target.to_string()));
         }
     }
 
-    async fn download(&self, client: &Client, isbn: &str, file_name: &str) -> api::Result<PathBuf> {
-        /* Request link */
-        let license = client.license(isbn).await?;
+    Ok(feed)
+}
 
-        let url = license._links.download.unwrap();
+/* Serve the feed, rebuilding it in the background rather than on requests */
+async fn serve_feed(
+    client: &Client,
+    preferences: &Preferences,
+    days: i64,
+    address: &str,
+) -> api::Result<()> {
+    const REFRESH: std::time::Duration = std::time::Duration::from_secs(15 * 60);
 
-        let mut path = self.path.clone();
-        path.push(file_name.replace('/', "_"));
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(api::Error::from_io)?;
+    log::info!("Serving feed on http://{address}/");
 
-        let mut file = File::create(&path).await.unwrap();
+    let (atom, cached) = tokio::sync::watch::channel(None);
+    let refresh = async {
+        loop {
+            match build_feed(client, preferences, days).await {
+                Ok(mut feed) => {
+                    atom.send_replace(Some(feed.to_atom()));
+                }
+                Err(err) => log::error!("Failed to build feed: {:?}", err),
+            }
+            tokio::time::sleep(REFRESH).await;
+        }
+    };
 
-        let response = self
-            .client
-            .get(url.href)
-            .send()
-            .await
-            .map_err(api::Error::from_reqwest)?;
+    tokio::select! {
+        result = feed::serve(listener, cached) => result,
+        _ = refresh => Ok(()),
+    }
+}
 
-        let status = response.status();
-        if !status.is_success() {
-            let error = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "(Unknown)".to_owned());
-            return Err(api::Error::Cdn(status.as_u16(), error));
-        }
+fn export(mut args: pico_args::Arguments) -> api::Result<()> {
+    let dest = valid(args.opt_value_from_str::<&str, PathBuf>("--output"))
+        .unwrap_or_else(|| std::env::current_dir().unwrap());
+    let format = valid(args.opt_value_from_str("--format")).unwrap_or(ExportFormat::Csv);
+    let file = valid(args.opt_value_from_str::<&str, PathBuf>("--file"));
 
-        let bar =
-            indicatif::ProgressBar::new(license.filesize as u64).with_style(self.style.clone());
+    let library = Library::load(&dest)?;
+    let export = export::export(&library, format)?;
 
-        let mut stream = response.bytes_stream();
-        while let Some(Ok(mut chunk)) = stream.next().await {
-            bar.inc(chunk.len() as u64);
-            let _ = file.write_all_buf(&mut chunk).await;
+    match file {
+        Some(path) => fs::write(path, export).map_err(api::Error::from_io),
+        None => {
+            print!("{export}");
+            Ok(())
         }
+    }
+}
 
-        bar.finish_and_clear();
+fn watch(mut args: pico_args::Arguments) -> api::Result<()> {
+    let mut watchlist = Watchlist::load(WATCHLIST_PATH.as_ref())?;
+
+    let action = args.subcommand().unwrap();
+    if action.as_deref() == Some("list") {
+        for watch in &watchlist.entries {
+            let last_sync = watch
+                .last_sync
+                .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "never".to_owned());
+            println!("{} (last sync: {})", watch.target, last_sync);
+        }
+        return Ok(());
+    }
+
+    let mut targets = Vec::new();
+    while let Ok(Some(name)) = args.opt_value_from_str::<&str, String>("--author") {
+        targets.push(Target::Author(name));
+    }
+    while let Ok(Some(name)) = args.opt_value_from_str::<&str, String>("--narrator") {
+        targets.push(Target::Narrator(name));
+    }
+    while let Ok(Some(id)) = args.opt_value_from_str::<&str, u32>("--series") {
+        targets.push(Target::Series(id));
+    }
 
-        Ok(path)
+    /* Adding or removing nothing is most likely a typo */
+    if targets.is_empty() {
+        eprintln!("{}", USAGE);
+        return Ok(());
     }
+
+    match action.as_deref() {
+        Some("add") => {
+            let languages: Vec<Language> = valid(args.values_from_str("--language"));
+            let audiobook = args.opt_value_from_str("--audiobook").unwrap();
+            let ebook = args.opt_value_from_str("--ebook").unwrap();
+
+            for target in targets {
+                let watch = Watch {
+                    languages: languages.clone(),
+                    audiobook,
+                    ebook,
+                    ..Watch::new(target)
+                };
+                if !watchlist.add(watch) {
+                    eprintln!("Already watching");
+                }
+            }
+        }
+        Some("remove") => {
+            for target in targets {
+                if !watchlist.remove(&target) {
+                    eprintln!("Not watching {target}");
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return Ok(());
+        }
+    }
+
+    watchlist.save()
 }
 
-async fn set_m4a_metadata(path: &PathBuf, book: &client::SearchBook) {
-    let mut tag = mp4ameta::Tag::read_from_path(path).unwrap();
+/* Keep the files the licensing limit didn't allow for "queue run" */
+/* Work through the queue, waiting for the licensing limit to reset unless told not to */
+async fn run_queue(
+    mut client: Client,
+    downloader: &Downloader,
+    dest: &Path,
+    wait: bool,
+) -> api::Result<()> {
+    let mut queue = Queue::load(dest)?;
+    if queue.is_empty() {
+        log::info!("Nothing queued");
+        return Ok(());
+    }
 
-    tag.set_title(&book.title);
-    tag.set_year(book.published.year().to_string());
-    tag.set_album(&book.title);
-    tag.set_artist(&book.author);
-    tag.set_album_artist(&book.author);
+    /* The reported reset is unreliable, so always try before waiting for it.
+    Failed files stay queued and are skipped over. */
+    let cancel = downloader.cancel();
+    let mut completed = Vec::new();
+    let mut limited = false;
+    let mut index = 0;
+    while index < queue.len() {
+        if limited {
+            let reset = queue.reset.unwrap().format("%Y-%m-%d %H:%M UTC");
+            if !wait {
+                log::info!(
+                    "Licensing limit reached until {}, {} file(s) queued",
+                    reset,
+                    queue.len()
+                );
+                return Ok(());
+            }
 
-    let Some(image) = &book.image else {
-        eprintln!("No image set");
-        return;
-    };
+            log::info!("Waiting for the licensing limit to reset at {}", reset);
+            tokio::select! {
+                _ = tokio::time::sleep(queue.until_reset(chrono::Utc::now())) => {}
+                _ = cancel.stopped() => {
+                    summarize(&completed, queue.items());
+                    return Ok(());
+                }
+            }
+            limited = false;
+            if client.refresh_if_expired().await? {
+                write_token(client.extract_token()).await;
+            }
+        }
 
-    let Ok(response) = reqwest::get(image).await else{
-        eprintln!("Failed to download cover");
-        return;
-    };
+        let item = queue.items()[index].clone();
+        match downloader.download_item(&client, &item).await {
+            Ok(download) => {
+                queue.remove(index);
+                queue.reset = None;
+                completed.push(download);
+            }
+            Err(api::Error::Cancelled) => {
+                summarize(&completed, queue.items());
+                log::info!("The pending files stay queued");
+                return Ok(());
+            }
+            Err(api::Error::WindowClosed) => {
+                log::info!("The download window closed, {} stays queued", item.isbn);
+            }
+            Err(err) if err.is_quota_exceeded() => {
+                limited = true;
+                /* A reset in the past would have us asking again right away */
+                queue.limit_reached(client.quota_reset(), chrono::Utc::now());
+            }
+            Err(err) => {
+                log::error!("Failed to download {}: {:?}", item.isbn, err);
+                index += 1;
+            }
+        }
+        queue.save()?;
+    }
 
-    let format = match response.headers().get("content-type").map(|v| v.to_str()) {
-        Some(Ok("image/jpeg")) => mp4ameta::ImgFmt::Jpeg,
-        Some(Ok("image/png")) => mp4ameta::ImgFmt::Png,
-        Some(Ok(format)) => {
-            eprintln!("Unknown image format {format}");
-            return;
+    if !queue.is_empty() {
+        log::warn!("{} file(s) failed and stay queued", queue.len());
+    }
+    Ok(())
+}
+
+fn queue(mut args: pico_args::Arguments, action: Option<&str>) -> api::Result<()> {
+    let dest = args
+        .opt_value_from_str::<&str, PathBuf>("--output")
+        .unwrap()
+        .unwrap_or_else(|| std::env::current_dir().unwrap());
+    let mut queue = Queue::load(&dest)?;
+
+    match action {
+        Some("list") => {
+            for (index, item) in queue.items().iter().enumerate() {
+                println!(
+                    "{:3} {} {:4} {}",
+                    index + 1,
+                    item.isbn,
+                    item.format.extension(),
+                    item.file_name()
+                );
+            }
+            if let Some(reset) = queue.reset {
+                println!(
+                    "Licensing limit resets at {}",
+                    reset.format("%Y-%m-%d %H:%M UTC")
+                );
+            }
+            return Ok(());
+        }
+        Some("remove") => {
+            let mut indices = Vec::new();
+            while let Some(key) = args.opt_free_from_str::<String>().unwrap() {
+                match queue.find(&key) {
+                    Some(index) => indices.push(index),
+                    None => eprintln!("Not queued: {key}"),
+                }
+            }
+            indices.sort_unstable();
+            indices.dedup();
+            for index in indices.into_iter().rev() {
+                queue.remove(index);
+            }
+        }
+        Some("reorder") => {
+            let key: String = valid(args.free_from_str());
+            let position: usize = valid(args.free_from_str());
+            let Some(index) = queue.find(&key) else {
+                eprintln!("Not queued: {key}");
+                return Ok(());
+            };
+            queue.reorder(index, position.saturating_sub(1));
         }
         _ => {
-            eprintln!("Unknown image format (undefined)");
-            return;
+            eprintln!("{}", USAGE);
+            return Ok(());
         }
-    };
+    }
 
-    let bytes = response.bytes().await.unwrap();
-    let img = mp4ameta::Img::new(format, bytes);
-    tag.add_artwork(img);
+    queue.save()
+}
 
-    tag.write_to_path(path).unwrap();
+async fn confirm(message: &str) -> bool {
+    eprintln!("{} [y/N]", message);
+    let answer = stdin().read_u8().await.expect("Aborted");
+    matches!(answer, b'y' | b'Y')
+}
+
+async fn write_token(token: &client::AuthToken) {
+    let token = serde_json::to_vec(token).unwrap();
+    std::fs::write(TOKEN_PATH, token).unwrap();
 }
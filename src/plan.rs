@@ -0,0 +1,408 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    client::{Book, BookFormat, DateTime, Edition, SearchBook, Series, SeriesPart},
+    download::Metadata,
+    locale::Market,
+};
+
+/// Which editions of a format to download when a book has several, e.g. an
+/// abridged and an unabridged audiobook. Each one costs a license.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EditionPolicy {
+    /// The most recently published one
+    #[default]
+    Newest,
+    Oldest,
+    /// The newest by this publisher, otherwise the newest of all
+    Publisher(String),
+    /// This edition, otherwise the newest
+    Isbn(String),
+    All,
+}
+
+impl EditionPolicy {
+    pub fn pick<'a>(&self, editions: &[&'a Edition]) -> Vec<&'a Edition> {
+        let newest = || {
+            editions
+                .iter()
+                .copied()
+                .max_by_key(|edition| edition.published)
+        };
+
+        let picked = match self {
+            Self::All => return editions.to_vec(),
+            Self::Newest => newest(),
+            Self::Oldest => editions
+                .iter()
+                .copied()
+                .min_by_key(|edition| edition.published),
+            Self::Publisher(publisher) => editions
+                .iter()
+                .copied()
+                .filter(|edition| edition.publisher.eq_ignore_ascii_case(publisher))
+                .max_by_key(|edition| edition.published)
+                .or_else(newest),
+            Self::Isbn(isbn) => editions
+                .iter()
+                .copied()
+                .find(|edition| &edition.isbn == isbn)
+                .or_else(newest),
+        };
+
+        picked.into_iter().collect()
+    }
+}
+
+impl FromStr for EditionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("publisher", publisher)) => Ok(Self::Publisher(publisher.to_owned())),
+            Some(("isbn", isbn)) => Ok(Self::Isbn(isbn.to_owned())),
+            _ => match s.to_ascii_lowercase().as_str() {
+                "newest" => Ok(Self::Newest),
+                "oldest" => Ok(Self::Oldest),
+                "all" => Ok(Self::All),
+                _ => Err(format!(
+                    "Unknown edition policy \"{s}\", expected newest, oldest, all, publisher:NAME or isbn:ISBN"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for EditionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Newest => f.write_str("newest"),
+            Self::Oldest => f.write_str("oldest"),
+            Self::Publisher(publisher) => write!(f, "publisher:{publisher}"),
+            Self::Isbn(isbn) => write!(f, "isbn:{isbn}"),
+            Self::All => f.write_str("all"),
+        }
+    }
+}
+
+/// One file to download, with everything the selectors found out about its book.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct WorkItem {
+    pub isbn: String,
+    pub format: BookFormat,
+    /// Market to license in, differs from the client's for `--any-market`
+    pub market: Market,
+    /// As found by a search, genre or series
+    pub search: Option<SearchBook>,
+    /// Full details, as looked up by id or ISBN
+    pub book: Option<Book>,
+    pub series: Option<String>,
+    pub part: Option<u32>,
+    /// The edition was chosen, by the edition policy or by its ISBN, rather
+    /// than being whichever one a listing happened to name
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl WorkItem {
+    pub fn id(&self) -> Option<usize> {
+        match (&self.book, &self.search) {
+            (Some(book), _) => Some(book.id),
+            (None, Some(book)) => Some(book.id),
+            (None, None) => None,
+        }
+    }
+
+    /// Full details take precedence over search results.
+    pub fn metadata(&self) -> Option<Metadata<'_>> {
+        let metadata = match (&self.book, &self.search) {
+            (Some(book), _) => Metadata::from(book),
+            (None, Some(book)) => Metadata::from(book),
+            (None, None) => return None,
+        };
+
+        Some(match &self.series {
+            Some(series) => metadata.with_series(series, self.part),
+            None => metadata,
+        })
+    }
+
+    /// All editions of the format when the book has details.
+    pub fn editions(&self) -> Vec<&Edition> {
+        self.book
+            .iter()
+            .flat_map(|book| &book.editions)
+            .filter(|edition| edition.format == self.format)
+            .collect()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        match (&self.book, &self.search) {
+            (Some(book), _) => Some(&book.title),
+            (None, Some(book)) => Some(&book.title),
+            (None, None) => None,
+        }
+    }
+
+    pub fn published(&self) -> Option<DateTime> {
+        match (&self.book, &self.search) {
+            (Some(book), _) => Some(book.published),
+            (None, Some(book)) => Some(book.published),
+            (None, None) => None,
+        }
+    }
+
+    /// Series parts are prefixed with their number, unknown books named by ISBN.
+    pub fn file_name(&self) -> String {
+        let extension = self.format.extension();
+        let Some(title) = self.title() else {
+            return format!("{}.{}", self.isbn, extension);
+        };
+
+        let prefix = match (&self.series, self.part) {
+            (Some(_), Some(part)) => format!("{:03} ", part),
+            _ => String::new(),
+        };
+        format!("{}{} ({}).{}", prefix, title, self.isbn, extension)
+    }
+
+    /* Another edition of the same book counts as the same file */
+    fn same_book(&self, other: &WorkItem) -> bool {
+        self.format == other.format
+            && (self.isbn == other.isbn || (self.id().is_some() && self.id() == other.id()))
+    }
+
+    /* Fill in what the other selector knew */
+    fn merge(&mut self, other: WorkItem) {
+        if self.book.is_none() {
+            self.book = other.book;
+        }
+        if self.search.is_none() {
+            self.search = other.search;
+        }
+        if self.series.is_none() {
+            self.series = other.series;
+            self.part = other.part;
+        }
+        self.pinned |= other.pinned;
+    }
+}
+
+/// Work items of all selectors of a run, every book and format only once
+/// unless several of its editions were chosen.
+#[derive(Debug, Default)]
+pub struct Plan {
+    items: Vec<WorkItem>,
+    duplicates: usize,
+    policy: EditionPolicy,
+}
+
+impl Plan {
+    pub fn new(policy: EditionPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn policy(&self) -> &EditionPolicy {
+        &self.policy
+    }
+
+    /// Returns false when the file was already planned, merging the metadata.
+    /// A chosen edition replaces the one a listing named for the same book.
+    pub fn push(&mut self, mut item: WorkItem) -> bool {
+        if let Some(known) = self
+            .items
+            .iter_mut()
+            .find(|known| known.isbn == item.isbn && known.format == item.format)
+        {
+            known.merge(item);
+            self.duplicates += 1;
+            return false;
+        }
+
+        let same: Vec<usize> = (0..self.items.len())
+            .filter(|&index| self.items[index].same_book(&item))
+            .collect();
+        if same.is_empty() {
+            self.items.push(item);
+            return true;
+        }
+
+        if !item.pinned {
+            self.items[same[0]].merge(item);
+            self.duplicates += 1;
+            return false;
+        }
+
+        /* Several chosen editions are all kept, e.g. for --edition all */
+        let listed: Vec<usize> = same
+            .into_iter()
+            .filter(|&index| !self.items[index].pinned)
+            .collect();
+        let Some(&first) = listed.first() else {
+            self.items.push(item);
+            return true;
+        };
+
+        for &index in listed.iter().rev() {
+            item.merge(self.items.remove(index));
+            self.duplicates += 1;
+        }
+        self.items.insert(first, item);
+        true
+    }
+
+    /// The `formats` of a search, genre or series hit that it has.
+    pub fn add_search(&mut self, book: &SearchBook, formats: &[BookFormat], market: Market) {
+        self.add_listed(book, None, None, formats, market);
+    }
+
+    pub fn add_part(
+        &mut self,
+        series: &Series,
+        part: &SeriesPart,
+        formats: &[BookFormat],
+        market: Market,
+    ) {
+        self.add_listed(
+            &part._embedded.book,
+            Some(&series.name),
+            part.partnumber,
+            formats,
+            market,
+        );
+    }
+
+    fn add_listed(
+        &mut self,
+        book: &SearchBook,
+        series: Option<&str>,
+        part: Option<u32>,
+        formats: &[BookFormat],
+        market: Market,
+    ) {
+        for &format in formats {
+            let isbn = match format {
+                BookFormat::AudioBook => &book.audiobookisbn,
+                BookFormat::EBook => &book.ebookisbn,
+            };
+            let Some(isbn) = isbn else {
+                continue;
+            };
+
+            self.push(WorkItem {
+                isbn: isbn.clone(),
+                format,
+                market,
+                search: Some(book.clone()),
+                book: None,
+                series: series.map(str::to_owned),
+                part,
+                pinned: false,
+            });
+        }
+    }
+
+    /// The editions of `format` the policy picks of a book looked up in `market`.
+    pub fn add_book(&mut self, book: &Book, format: BookFormat, market: Market) {
+        let editions: Vec<&Edition> = book
+            .editions
+            .iter()
+            .filter(|edition| edition.format == format)
+            .collect();
+
+        for edition in self.policy.pick(&editions) {
+            self.push(WorkItem {
+                isbn: edition.isbn.clone(),
+                format,
+                market,
+                search: None,
+                book: Some(book.clone()),
+                series: None,
+                part: None,
+                pinned: true,
+            });
+        }
+    }
+
+    /// Books of listed items, whose edition is up to the policy once their
+    /// details are known, see `add_details`.
+    pub fn unresolved(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = Vec::new();
+        for item in &self.items {
+            if let (false, None, Some(id)) = (item.pinned, &item.book, item.id()) {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        ids
+    }
+
+    /// Let the policy pick the editions of the listed items of this book.
+    pub fn add_details(&mut self, book: &Book) {
+        let mut index = 0;
+        while index < self.items.len() {
+            let item = &self.items[index];
+            if item.pinned || item.book.is_some() || item.id() != Some(book.id) {
+                index += 1;
+                continue;
+            }
+
+            let mut item = self.items.remove(index);
+            item.book = Some(book.clone());
+            item.pinned = true;
+
+            /* Keep what the listing named if the details know no better */
+            let mut isbns: Vec<String> = self
+                .policy
+                .pick(&item.editions())
+                .iter()
+                .map(|edition| edition.isbn.clone())
+                .collect();
+            if isbns.is_empty() {
+                isbns.push(item.isbn.clone());
+            }
+
+            for isbn in isbns {
+                let edition = WorkItem {
+                    isbn,
+                    ..item.clone()
+                };
+                let known = self
+                    .items
+                    .iter_mut()
+                    .find(|known| known.isbn == edition.isbn && known.format == edition.format);
+                match known {
+                    Some(known) => {
+                        known.merge(edition);
+                        self.duplicates += 1;
+                    }
+                    None => {
+                        self.items.insert(index, edition);
+                        index += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn items(&self) -> &[WorkItem] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// How many selector hits were already planned by another selector.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
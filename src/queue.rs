@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    api::{Error, Result},
+    client::DateTime,
+    plan::WorkItem,
+};
+
+const QUEUE_FILE: &str = "queue.json";
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+struct Stored {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reset: Option<DateTime>,
+    items: Vec<WorkItem>,
+}
+
+/// Downloads left over when the licensing quota ran out, stored as
+/// `queue.json` next to the books until `queue run` picks them up.
+pub struct Queue {
+    path: PathBuf,
+    /// When the quota renews, as reported when it ran out
+    pub reset: Option<DateTime>,
+    items: Vec<WorkItem>,
+}
+
+impl Queue {
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(QUEUE_FILE);
+        let stored: Stored = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(Error::from_serde)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Stored::default(),
+            Err(err) => return Err(Error::from_io(err)),
+        };
+
+        Ok(Self {
+            path,
+            reset: stored.reset,
+            items: stored.items,
+        })
+    }
+
+    /// Removes the file once the queue is empty.
+    pub fn save(&self) -> Result<()> {
+        if self.items.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::from_io(err)),
+                _ => Ok(()),
+            };
+        }
+
+        let stored = Stored {
+            reset: self.reset,
+            items: self.items.clone(),
+        };
+        let data = serde_json::to_vec_pretty(&stored).map_err(Error::from_serde)?;
+
+        /* Never lose the queue to a half written file */
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, data).map_err(Error::from_io)?;
+        std::fs::rename(&temp, &self.path).map_err(Error::from_io)
+    }
+
+    pub fn items(&self) -> &[WorkItem] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns false if the file is already queued.
+    pub fn push(&mut self, item: WorkItem) -> bool {
+        if self
+            .items
+            .iter()
+            .any(|queued| queued.isbn == item.isbn && queued.format == item.format)
+        {
+            return false;
+        }
+        self.items.push(item);
+        true
+    }
+
+    /// Position of an item given by its 1-based position or ISBN.
+    pub fn find(&self, key: &str) -> Option<usize> {
+        match key.parse::<usize>() {
+            Ok(position) if (1..=self.items.len()).contains(&position) => Some(position - 1),
+            _ => self.items.iter().position(|item| item.isbn == key),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> WorkItem {
+        self.items.remove(index)
+    }
+
+    /// Move an item to another index, clamped to the end of the queue.
+    pub fn reorder(&mut self, from: usize, to: usize) {
+        let item = self.items.remove(from);
+        let to = to.min(self.items.len());
+        self.items.insert(to, item);
+    }
+
+    /// Wait for the reported reset, or an hour when it isn't in the future.
+    pub fn limit_reached(&mut self, reported: Option<DateTime>, now: DateTime) {
+        self.reset = reported
+            .filter(|&reset| reset > now)
+            .or(Some(now + chrono::Duration::hours(1)));
+    }
+
+    /// Time left until the quota renews, zero once it has.
+    pub fn until_reset(&self, now: DateTime) -> std::time::Duration {
+        self.reset
+            .and_then(|reset| (reset - now).to_std().ok())
+            .unwrap_or_default()
+    }
+}
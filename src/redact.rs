@@ -0,0 +1,60 @@
+use reqwest::header::HeaderValue;
+use serde_json::Value;
+
+pub const REDACTED: &str = "REDACTED";
+/* JSON fields holding credentials or personal data, in requests and responses */
+const SECRET_FIELDS: [&str; 8] = [
+    "username",
+    "password",
+    "token",
+    "refreshtoken",
+    "email",
+    "firstname",
+    "lastname",
+    "displayname",
+];
+
+/// Blank out credentials and the signatures of download links.
+pub fn json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) && value.is_string() {
+                    *value = Value::String(REDACTED.to_owned());
+                } else if key == "href" {
+                    if let Some(href) = value.as_str() {
+                        *value = Value::String(url(href).to_owned());
+                    }
+                } else {
+                    json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(json),
+        _ => {}
+    }
+}
+
+/// A printable body, redacted when it is JSON.
+pub fn body(body: &[u8]) -> String {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            json(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!("({} bytes)", body.len()),
+    }
+}
+
+/// CDN links are signed in their query.
+pub fn url(url: &str) -> &str {
+    url.split('?').next().unwrap_or_default()
+}
+
+pub fn header<'a>(name: &str, value: &'a HeaderValue) -> &'a str {
+    if name.eq_ignore_ascii_case("authorization") || name.eq_ignore_ascii_case("cookie") {
+        REDACTED
+    } else {
+        value.to_str().unwrap_or("(binary)")
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    client::{BookFormat, SearchBook},
+    locale::Market,
+    plan::WorkItem,
+};
+
+/// A search hit or a part of the series being browsed.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub book: SearchBook,
+    pub series: Option<String>,
+    pub part: Option<u32>,
+}
+
+impl Entry {
+    pub fn isbn(&self, format: BookFormat) -> Option<&String> {
+        match format {
+            BookFormat::AudioBook => self.book.audiobookisbn.as_ref(),
+            BookFormat::EBook => self.book.ebookisbn.as_ref(),
+        }
+    }
+}
+
+/// Formats picked for a book.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub entry: Entry,
+    pub audiobook: bool,
+    pub ebook: bool,
+}
+
+/// Books picked for download, each with at least one format.
+#[derive(Debug, Default)]
+pub struct Selections {
+    items: Vec<Selection>,
+}
+
+impl Selections {
+    pub fn get(&self, id: usize) -> Option<&Selection> {
+        self.items
+            .iter()
+            .find(|selection| selection.entry.book.id == id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Unselect the book, or pick the `preferred` formats it has, or
+    /// whatever it has.
+    pub fn toggle(&mut self, entry: &Entry, preferred: &[BookFormat]) {
+        let id = entry.book.id;
+        if self.get(id).is_some() {
+            self.items.retain(|selection| selection.entry.book.id != id);
+            return;
+        }
+
+        let has_audiobook = entry.book.audiobookisbn.is_some();
+        let has_ebook = entry.book.ebookisbn.is_some();
+        let mut audiobook = preferred.contains(&BookFormat::AudioBook) && has_audiobook;
+        let mut ebook = preferred.contains(&BookFormat::EBook) && has_ebook;
+        if !audiobook && !ebook {
+            audiobook = has_audiobook;
+            ebook = !has_audiobook && has_ebook;
+        }
+
+        self.items.push(Selection {
+            entry: entry.clone(),
+            audiobook,
+            ebook,
+        });
+    }
+
+    /// Returns false when the book has no edition of the format.
+    pub fn toggle_format(&mut self, entry: &Entry, format: BookFormat) -> bool {
+        if entry.isbn(format).is_none() {
+            return false;
+        }
+
+        let id = entry.book.id;
+        let index = match self
+            .items
+            .iter()
+            .position(|selection| selection.entry.book.id == id)
+        {
+            Some(index) => index,
+            None => {
+                self.items.push(Selection {
+                    entry: entry.clone(),
+                    audiobook: false,
+                    ebook: false,
+                });
+                self.items.len() - 1
+            }
+        };
+
+        let selection = &mut self.items[index];
+        match format {
+            BookFormat::AudioBook => selection.audiobook = !selection.audiobook,
+            BookFormat::EBook => selection.ebook = !selection.ebook,
+        }
+        self.items
+            .retain(|selection| selection.audiobook || selection.ebook);
+        true
+    }
+
+    /// One work item per selected format, clearing the selection.
+    pub fn take(&mut self, market: Market) -> Vec<WorkItem> {
+        let mut items = Vec::new();
+        for selection in std::mem::take(&mut self.items) {
+            let formats = [
+                (selection.audiobook, BookFormat::AudioBook),
+                (selection.ebook, BookFormat::EBook),
+            ];
+
+            for (enabled, format) in formats {
+                let Some(isbn) = selection.entry.isbn(format).filter(|_| enabled) else {
+                    continue;
+                };
+
+                items.push(WorkItem {
+                    isbn: isbn.clone(),
+                    format,
+                    market,
+                    search: Some(selection.entry.book.clone()),
+                    book: None,
+                    series: selection.entry.series.clone(),
+                    part: selection.entry.part,
+                    pinned: false,
+                });
+            }
+        }
+        items
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Queued,
+    Running(u64, u64),
+    Done,
+    Failed(String),
+    /// Moved to `queue.json` by the licensing limit or the window
+    Deferred,
+}
+
+impl State {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed(_) | Self::Deferred)
+    }
+}
+
+#[derive(Debug)]
+pub struct Queued {
+    pub file_name: String,
+    pub isbn: String,
+    pub state: State,
+}
+
+/// Reported by the download worker, by ISBN.
+#[derive(Debug)]
+pub enum Update {
+    Progress(String, u64, u64),
+    Finished(String, Result<(), String>),
+    Deferred(String),
+}
+
+/// Downloads queued during a session, in order.
+#[derive(Debug, Default)]
+pub struct Downloads {
+    items: Vec<Queued>,
+}
+
+impl Downloads {
+    pub fn push(&mut self, item: &WorkItem) {
+        self.items.push(Queued {
+            file_name: item.file_name(),
+            isbn: item.isbn.clone(),
+            state: State::Queued,
+        });
+    }
+
+    /// Applies to the first unfinished download of the ISBN, the one the
+    /// worker is on when the same file was queued again. Returns it if any.
+    pub fn update(&mut self, update: Update) -> Option<&Queued> {
+        let isbn = match &update {
+            Update::Progress(isbn, _, _) | Update::Finished(isbn, _) | Update::Deferred(isbn) => {
+                isbn
+            }
+        };
+        let queued = self
+            .items
+            .iter_mut()
+            .find(|queued| &queued.isbn == isbn && !queued.state.is_finished())?;
+
+        queued.state = match update {
+            Update::Progress(_, done, total) => State::Running(done, total),
+            Update::Finished(_, Ok(())) => State::Done,
+            Update::Finished(_, Err(err)) => State::Failed(err),
+            Update::Deferred(_) => State::Deferred,
+        };
+        Some(queued)
+    }
+
+    pub fn items(&self) -> &[Queued] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn unfinished(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|queued| !queued.state.is_finished())
+            .count()
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Datelike;
+
+use crate::{
+    api::{Error, Result},
+    client::Book,
+    xml::escape,
+};
+
+/// Audiobookshelf `metadata.json`.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Abs<'a> {
+    title: &'a str,
+    authors: Vec<&'a str>,
+    narrators: Vec<&'a str>,
+    series: Vec<String>,
+    genres: Vec<&'a str>,
+    published_year: String,
+    published_date: String,
+    publisher: Option<&'a str>,
+    description: &'a str,
+    isbn: &'a str,
+    language: &'a str,
+}
+
+/// Everything the sidecar files describe.
+pub struct Sidecar<'a> {
+    pub book: &'a Book,
+    pub isbn: &'a str,
+    pub series: Option<&'a str>,
+    pub part: Option<u32>,
+}
+
+impl<'a> Sidecar<'a> {
+    fn publisher(&self) -> Option<&'a str> {
+        let editions = &self.book.editions;
+        editions
+            .iter()
+            .find(|edition| edition.isbn == self.isbn)
+            .or_else(|| editions.first())
+            .map(|edition| edition.publisher.as_str())
+    }
+
+    fn abs(&self) -> Abs<'a> {
+        let book = self.book;
+        let series = match (self.series, self.part) {
+            (Some(series), Some(part)) => vec![format!("{series} #{part}")],
+            (Some(series), None) => vec![series.to_owned()],
+            _ => Vec::new(),
+        };
+
+        Abs {
+            title: &book.title,
+            authors: split_names(&book.author),
+            narrators: split_names(&book.narrator),
+            series,
+            genres: book
+                .genres
+                .iter()
+                .map(|genre| genre.name.as_str())
+                .collect(),
+            published_year: book.published.year().to_string(),
+            published_date: book.published.format("%Y-%m-%d").to_string(),
+            publisher: self.publisher(),
+            description: &book.summary,
+            isbn: self.isbn,
+            language: &book.language,
+        }
+    }
+
+    fn opf(&self) -> String {
+        let book = self.book;
+        let mut opf = String::new();
+
+        opf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        opf.push_str("<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"2.0\" unique-identifier=\"isbn\">\n");
+        opf.push_str("  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n");
+
+        let _ = writeln!(opf, "    <dc:title>{}</dc:title>", escape(&book.title));
+        for author in split_names(&book.author) {
+            let _ = writeln!(
+                opf,
+                "    <dc:creator opf:role=\"aut\">{}</dc:creator>",
+                escape(author)
+            );
+        }
+        for narrator in split_names(&book.narrator) {
+            let _ = writeln!(
+                opf,
+                "    <dc:contributor opf:role=\"nrt\">{}</dc:contributor>",
+                escape(narrator)
+            );
+        }
+        let _ = writeln!(
+            opf,
+            "    <dc:description>{}</dc:description>",
+            escape(&book.summary)
+        );
+        if let Some(publisher) = self.publisher() {
+            let _ = writeln!(
+                opf,
+                "    <dc:publisher>{}</dc:publisher>",
+                escape(publisher)
+            );
+        }
+        let _ = writeln!(
+            opf,
+            "    <dc:date>{}</dc:date>",
+            book.published.format("%Y-%m-%d")
+        );
+        let _ = writeln!(
+            opf,
+            "    <dc:language>{}</dc:language>",
+            escape(&book.language)
+        );
+        for genre in &book.genres {
+            let _ = writeln!(opf, "    <dc:subject>{}</dc:subject>", escape(&genre.name));
+        }
+        let _ = writeln!(
+            opf,
+            "    <dc:identifier id=\"isbn\" opf:scheme=\"ISBN\">{}</dc:identifier>",
+            escape(self.isbn)
+        );
+        if let Some(series) = self.series {
+            let _ = writeln!(
+                opf,
+                "    <meta name=\"calibre:series\" content=\"{}\"/>",
+                escape(series)
+            );
+        }
+        if let Some(part) = self.part {
+            let _ = writeln!(
+                opf,
+                "    <meta name=\"calibre:series_index\" content=\"{part}\"/>"
+            );
+        }
+
+        opf.push_str("  </metadata>\n</package>\n");
+        opf
+    }
+
+    /// Write `metadata.json`, `desc.txt`, `reader.txt` and `metadata.opf` for
+    /// the book stored at `path`. Books sharing a folder get the files
+    /// prefixed with their own file name instead.
+    pub async fn write(&self, path: &Path, own_directory: bool) -> Result<()> {
+        let abs = serde_json::to_vec_pretty(&self.abs()).map_err(Error::from_serde)?;
+
+        let files = [
+            ("metadata.json", abs),
+            ("desc.txt", self.book.summary.as_bytes().to_vec()),
+            ("reader.txt", self.book.narrator.as_bytes().to_vec()),
+            ("metadata.opf", self.opf().into_bytes()),
+        ];
+
+        for (name, contents) in files {
+            let target = sidecar_path(path, name, own_directory);
+            tokio::fs::write(&target, contents)
+                .await
+                .map_err(Error::from_io)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn sidecar_path(book: &Path, name: &str, own_directory: bool) -> PathBuf {
+    if own_directory {
+        book.with_file_name(name)
+    } else {
+        let stem = book.file_stem().unwrap_or_default().to_string_lossy();
+        book.with_file_name(format!("{stem}.{name}"))
+    }
+}
+
+fn split_names(names: &str) -> Vec<&str> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
@@ -0,0 +1,73 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::api::{Error, Result};
+
+const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+/// Byte count parsed from e.g. `1500`, `500k`, `2M` or `1.5G`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size(pub u64);
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid size \"{s}\", expected e.g. 500M or 2G");
+
+        let (number, unit) = match s.trim().to_ascii_lowercase() {
+            s if s.ends_with('k') => (s[..s.len() - 1].to_owned(), 1 << 10),
+            s if s.ends_with('m') => (s[..s.len() - 1].to_owned(), 1 << 20),
+            s if s.ends_with('g') => (s[..s.len() - 1].to_owned(), 1 << 30),
+            s => (s, 1),
+        };
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        if !number.is_finite() || number < 0.0 {
+            return Err(invalid());
+        }
+        Ok(Self((number * unit as f64) as u64))
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            write!(f, "{} B", self.0)
+        } else {
+            write!(f, "{:.1} {}", value, UNITS[unit])
+        }
+    }
+}
+
+/// Space available to us on the filesystem holding `path`. Asks the
+/// closest existing parent while the folder isn't created yet.
+pub fn available(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or_else(|| Path::new("."));
+    fs2::available_space(existing).map_err(Error::from_io)
+}
+
+/// Fail unless `needed` bytes fit into `path` with `reserve` left over.
+pub fn check(path: &Path, needed: u64, reserve: u64, what: &str) -> Result<()> {
+    let free = available(path)?;
+    if needed.saturating_add(reserve) <= free {
+        return Ok(());
+    }
+
+    Err(Error::DiskSpace(format!(
+        "{} needed {}, but only {} free in {} (keeping {} in reserve)",
+        Size(needed),
+        what,
+        Size(free),
+        path.display(),
+        Size(reserve)
+    )))
+}
@@ -0,0 +1,190 @@
+use std::{fmt, str::FromStr};
+
+use crate::client::DateTime;
+
+const OPERATIONAL: &str = "OK";
+/* Types the status page uses for partial outages and planned work */
+const DEGRADED: [&str; 5] = ["DEGRADED", "PARTIAL", "MINOR", "WARNING", "MAINTENANCE"];
+
+/// Payload of the BookBeat status page.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct ServiceStatus {
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub components: Vec<Component>,
+    #[serde(default)]
+    pub incidents: Vec<Incident>,
+    #[serde(default)]
+    pub messages: Vec<StatusMessage>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct Component {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct Incident {
+    pub title: String,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub started: Option<DateTime>,
+    #[serde(default)]
+    pub resolved: Option<DateTime>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct StatusMessage {
+    #[serde(default)]
+    pub title: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Health {
+    Operational,
+    Degraded,
+    Down,
+}
+
+/// When to refuse logging in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatusPolicy {
+    /// Only log in when everything is operational
+    Strict,
+    /// Warn on degraded service, refuse when it is down
+    #[default]
+    AllowDegraded,
+    /// Warn and log in regardless
+    Ignore,
+}
+
+impl FromStr for StatusPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(Self::Strict),
+            "degraded" => Ok(Self::AllowDegraded),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err(format!("Unknown status policy \"{s}\"")),
+        }
+    }
+}
+
+impl StatusPolicy {
+    pub fn allows(&self, health: Health) -> bool {
+        match self {
+            Self::Strict => health == Health::Operational,
+            Self::AllowDegraded => health <= Health::Degraded,
+            Self::Ignore => true,
+        }
+    }
+}
+
+fn health(typ: &str) -> Health {
+    let typ = typ.to_ascii_uppercase();
+    if typ == OPERATIONAL {
+        Health::Operational
+    } else if DEGRADED.contains(&typ.as_str()) {
+        Health::Degraded
+    } else {
+        Health::Down
+    }
+}
+
+impl ServiceStatus {
+    /// An operational service with failing components counts as degraded.
+    pub fn health(&self) -> Health {
+        let overall = health(&self.typ);
+        let components = self
+            .components
+            .iter()
+            .map(|component| health(&component.typ).min(Health::Degraded))
+            .max()
+            .unwrap_or(Health::Operational);
+        overall.max(components)
+    }
+
+    /// Incidents that haven't been resolved yet.
+    pub fn open_incidents(&self) -> impl Iterator<Item = &Incident> {
+        self.incidents
+            .iter()
+            .filter(|incident| incident.resolved.is_none())
+    }
+
+    /// One line for warnings, e.g. "DEGRADED: Search is slow".
+    pub fn summary(&self) -> String {
+        let detail = self.message.as_deref().or_else(|| {
+            self.open_incidents()
+                .next()
+                .map(|incident| incident.title.as_str())
+        });
+        match detail {
+            Some(detail) => format!("{}: {}", self.typ, detail),
+            None => self.typ.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ServiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Status: {}", self.typ)?;
+        if let Some(message) = &self.message {
+            writeln!(f, "  {message}")?;
+        }
+
+        if !self.components.is_empty() {
+            writeln!(f, "\nComponents:")?;
+            for component in &self.components {
+                write!(f, "  {:24} {}", component.name, component.typ)?;
+                if let Some(description) = &component.description {
+                    write!(f, " ({description})")?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        if !self.incidents.is_empty() {
+            writeln!(f, "\nIncidents:")?;
+            for incident in &self.incidents {
+                write!(f, "  {}", incident.title)?;
+                if let Some(status) = &incident.status {
+                    write!(f, " [{status}]")?;
+                }
+                if let Some(started) = incident.started {
+                    write!(f, " since {}", started.format("%Y-%m-%d %H:%M"))?;
+                }
+                if let Some(resolved) = incident.resolved {
+                    write!(f, ", resolved {}", resolved.format("%Y-%m-%d %H:%M"))?;
+                }
+                writeln!(f)?;
+                if let Some(message) = &incident.message {
+                    writeln!(f, "    {message}")?;
+                }
+            }
+        }
+
+        if !self.messages.is_empty() {
+            writeln!(f, "\nMessages:")?;
+            for message in &self.messages {
+                match &message.title {
+                    Some(title) => writeln!(f, "  {}: {}", title, message.text)?,
+                    None => writeln!(f, "  {}", message.text)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
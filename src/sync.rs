@@ -0,0 +1,117 @@
+use crate::{
+    api::Result,
+    client::{BookFormat, Client, DateTime},
+    download::{Batch, Downloader, Stop},
+    filter::Content,
+    plan::{Plan, WorkItem},
+    watchlist::{Target, Watch, Watchlist},
+};
+
+/// Formats and content settings a watchlist entry is synced with.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub formats: Vec<BookFormat>,
+    pub content: Content,
+}
+
+/// Files of the entry that aren't in the library yet, in listing order.
+pub async fn missing(
+    client: &Client,
+    downloader: &Downloader,
+    watch: &Watch,
+    settings: &Settings,
+) -> Result<Vec<WorkItem>> {
+    let market = client.market();
+    let content = &settings.content;
+    let mut plan = Plan::default();
+
+    match &watch.target {
+        Target::Author(name) | Target::Narrator(name) => {
+            let (author, narrator) = match &watch.target {
+                Target::Author(_) => (Some(name.as_str()), None),
+                _ => (None, Some(name.as_str())),
+            };
+            let books = client
+                .search_all(author, narrator, &content.languages, !content.sfw)
+                .await?;
+            for book in content.allowed(books, |book| book) {
+                plan.add_search(&book, &settings.formats, market);
+            }
+        }
+        Target::Series(id) => {
+            let series = client.series_all(*id).await?;
+            let parts = content.allowed(&series._embedded.parts, |part| &part._embedded.book);
+            for part in parts {
+                plan.add_part(&series, part, &settings.formats, market);
+            }
+        }
+    }
+
+    Ok(plan
+        .items()
+        .iter()
+        .filter(|item| !downloader.has(&item.isbn))
+        .cloned()
+        .collect())
+}
+
+/// Download what's missing of every entry, saving the watchlist after each.
+/// `settings` gives the settings of an entry. A stopped sync leaves the
+/// rest of the entry pending and the later entries to the next one.
+pub async fn run(
+    client: &Client,
+    downloader: &Downloader,
+    watchlist: &mut Watchlist,
+    settings: impl Fn(&Watch) -> Settings,
+) -> Result<Batch> {
+    let mut batch = Batch::default();
+    for index in 0..watchlist.entries.len() {
+        if downloader.cancel().is_stopped() {
+            batch.stopped = Some(Stop::Cancelled);
+            break;
+        }
+
+        let watch = &watchlist.entries[index];
+        log::info!("Syncing {}", watch.target);
+
+        let items = missing(client, downloader, watch, &settings(watch)).await?;
+        let before = batch.completed.len();
+        downloader.download_all(client, &items, &mut batch).await?;
+        for (item, download) in items.iter().zip(&batch.completed[before..]) {
+            let title = download.title.as_deref().unwrap_or(&download.isbn);
+            match item.published() {
+                Some(published) => log::info!(
+                    "  + {} ({}, {})",
+                    title,
+                    published.format("%Y-%m-%d"),
+                    kind(watch.last_sync, published)
+                ),
+                None => log::info!("  + {}", title),
+            }
+        }
+        if batch.stopped.is_some() {
+            break;
+        }
+        if items.is_empty() {
+            log::info!("  Up to date");
+        }
+
+        watchlist.entries[index].last_sync = Some(chrono::Utc::now());
+        watchlist.save()?;
+    }
+
+    if batch.stopped.is_none() {
+        log::info!("Downloaded {} new file(s)", batch.completed.len());
+    }
+
+    Ok(batch)
+}
+
+/* Whether a book is new since the last sync or was published before it */
+fn kind(last_sync: Option<DateTime>, published: DateTime) -> &'static str {
+    match last_sync {
+        Some(last_sync) if published > last_sync => "new release",
+        Some(_) => "backfill",
+        None => "initial",
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use chrono::{NaiveTime, Timelike};
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::space::Size;
+
+/// Transfer rate in bytes per second, parsed from e.g. `500k` or `2M`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(pub u64);
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid rate \"{s}\", expected bytes per second like 500k or 2M");
+
+        match s.parse() {
+            Ok(Size(rate)) if rate > 0 => Ok(Self(rate)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Keeps all transfers sharing it below one rate.
+pub struct RateLimit {
+    rate: Rate,
+    /// When the bytes handed out so far have passed at the rate
+    free: Mutex<Instant>,
+}
+
+impl RateLimit {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            free: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until `bytes` more fit into the rate. Idle time isn't saved up.
+    pub async fn take(&self, bytes: u64) {
+        let until = {
+            let mut free = self.free.lock().await;
+            let start = (*free).max(Instant::now());
+            *free = start + Duration::from_secs_f64(bytes as f64 / self.rate.0 as f64);
+            *free
+        };
+        tokio::time::sleep_until(until).await;
+    }
+}
+
+/// Daily time span in local time, e.g. `22:00-06:00`, in which transfers may start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Window {
+    /// Spans past midnight when the end is before the start.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+
+    /// Time left until the window opens, zero while it is open.
+    pub fn until_open(&self, time: NaiveTime) -> Duration {
+        if self.contains(time) {
+            return Duration::ZERO;
+        }
+
+        let seconds = |time: NaiveTime| i64::from(time.num_seconds_from_midnight());
+        let left = (seconds(self.start) - seconds(time)).rem_euclid(24 * 60 * 60);
+        Duration::from_secs(left as u64)
+    }
+
+    /// Sleep until the window opens.
+    pub async fn wait(&self) {
+        let left = self.until_open(chrono::Local::now().time());
+        if left.is_zero() {
+            return;
+        }
+
+        log::info!(
+            "Waiting for the download window {} ({}h {:02}m)",
+            self,
+            left.as_secs() / 3600,
+            left.as_secs() / 60 % 60
+        );
+        tokio::time::sleep(left).await;
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid window \"{s}\", expected e.g. 22:00-06:00");
+
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let time =
+            |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid());
+        let window = Self {
+            start: time(start)?,
+            end: time(end)?,
+        };
+        if window.start == window.end {
+            return Err(invalid());
+        }
+        Ok(window)
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
@@ -0,0 +1,700 @@
+use std::{
+    io::{self, Stdout},
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use tokio::sync::mpsc as queue;
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
+};
+
+use bookbeat::{
+    api::{self, Error},
+    client::{self, Book, BookFormat, Client, Search, Series},
+    download::{self, Downloader, Stop},
+    filter::Skipped,
+    logger::Logger,
+    plan::WorkItem,
+    selection::{Downloads, Entry, Selections, State, Update},
+};
+
+use crate::Preferences;
+
+const PAGE_SIZE: usize = 50;
+const QUEUE_HEIGHT: u16 = 8;
+const TICK: Duration = Duration::from_millis(100);
+const HELP: &str =
+    "Enter details  Space select  a/e formats  s series  d download  / search  Esc back  q quit";
+
+type Screen = Terminal<CrosstermBackend<Stdout>>;
+
+#[derive(PartialEq, Eq)]
+enum Focus {
+    Query,
+    Results,
+}
+
+enum Action {
+    Search,
+    Details,
+    Series,
+}
+
+/* What a network call in the background came back with */
+enum Loaded {
+    Results(String, Search),
+    Details(Book),
+    Series(Book, Option<Series>),
+}
+
+struct App<'a> {
+    client: Arc<Client>,
+    downloader: Arc<Downloader>,
+    jobs: queue::UnboundedSender<WorkItem>,
+    loaded: mpsc::Sender<api::Result<Loaded>>,
+    preferences: &'a Preferences,
+    focus: Focus,
+    query: String,
+    title: String,
+    entries: Vec<Entry>,
+    /// Search results to return to from a series
+    previous: Option<(String, Vec<Entry>)>,
+    list: ListState,
+    details: Vec<Book>,
+    selected: Selections,
+    queue: Downloads,
+    status: String,
+    quitting: bool,
+}
+
+/// Search, browse and queue downloads interactively until the user quits.
+pub async fn run(
+    client: Client,
+    dest: PathBuf,
+    mut options: download::Options,
+    preferences: &Preferences,
+) -> api::Result<()> {
+    let (updates, received) = mpsc::channel();
+
+    let progress = updates.clone();
+    let on_progress: download::OnProgress = Arc::new(move |isbn: &str, done, total| {
+        let _ = progress.send(Update::Progress(isbn.to_owned(), done, total));
+    });
+    options.on_progress = Some(on_progress);
+
+    let client = Arc::new(client);
+    let downloader = Arc::new(Downloader::new(dest, options)?);
+    let (jobs, pending) = queue::unbounded_channel();
+    let (loaded, results) = mpsc::channel();
+    let worker = tokio::spawn(work(client.clone(), downloader.clone(), pending, updates));
+
+    let mut app = App {
+        client,
+        downloader: downloader.clone(),
+        jobs,
+        loaded,
+        preferences,
+        focus: Focus::Query,
+        query: String::new(),
+        title: "Results".to_owned(),
+        entries: Vec::new(),
+        previous: None,
+        list: ListState::default(),
+        details: Vec::new(),
+        selected: Selections::default(),
+        queue: Downloads::default(),
+        status: String::new(),
+        quitting: false,
+    };
+
+    /* Messages would scribble over the screen, show them in the status line */
+    let (messages, logged) = mpsc::channel();
+    Logger::redirect(Some(messages));
+
+    let result = match enter() {
+        Ok(mut screen) => {
+            let result = app.run(&mut screen, &received, &results, &logged).await;
+            let left = leave(&mut screen).map_err(Error::from_io);
+            result.and(left)
+        }
+        Err(err) => Err(Error::from_io(err)),
+    };
+    Logger::redirect(None);
+
+    /* Even after an error, nothing may keep downloading behind the user's back */
+    let unfinished = app.unfinished();
+    drop(app);
+    if unfinished > 0 {
+        /* Lets the running download remove its partial file */
+        downloader.cancel().stop();
+        downloader.cancel().abort();
+        log::warn!("Abandoned {unfinished} queued downloads");
+    }
+    let _ = worker.await;
+
+    downloader.finish_batch().await;
+
+    result
+}
+
+fn enter() -> io::Result<Screen> {
+    terminal::enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    Terminal::new(CrosstermBackend::new(stdout))
+}
+
+fn leave(screen: &mut Screen) -> io::Result<()> {
+    terminal::disable_raw_mode()?;
+    execute!(screen.backend_mut(), LeaveAlternateScreen)?;
+    screen.show_cursor()
+}
+
+/* Download one queued file after the other */
+async fn work(
+    client: Arc<Client>,
+    downloader: Arc<Downloader>,
+    mut jobs: queue::UnboundedReceiver<WorkItem>,
+    updates: mpsc::Sender<Update>,
+) {
+    /* Once the licensing limit is reached the rest waits for "queue run" */
+    let mut limited = false;
+    while let Some(item) = jobs.recv().await {
+        let stop = if limited {
+            Stop::LimitReached
+        } else {
+            match downloader.download_item(&client, &item).await {
+                Err(err) if err.is_quota_exceeded() => {
+                    limited = true;
+                    Stop::LimitReached
+                }
+                Err(Error::WindowClosed) => Stop::WindowClosed,
+                result => {
+                    let result = result.map(|_| ()).map_err(|err| format!("{err:?}"));
+                    let _ = updates.send(Update::Finished(item.isbn, result));
+                    continue;
+                }
+            }
+        };
+
+        let update = match downloader.defer(&client, stop, std::slice::from_ref(&item)) {
+            Ok(()) => Update::Deferred(item.isbn),
+            Err(err) => Update::Finished(item.isbn, Err(format!("{err:?}"))),
+        };
+        let _ = updates.send(update);
+    }
+}
+
+impl App<'_> {
+    async fn run(
+        &mut self,
+        screen: &mut Screen,
+        updates: &mpsc::Receiver<Update>,
+        results: &mpsc::Receiver<api::Result<Loaded>>,
+        logged: &mpsc::Receiver<String>,
+    ) -> api::Result<()> {
+        loop {
+            for update in updates.try_iter() {
+                self.update(update);
+            }
+            for result in results.try_iter() {
+                match result {
+                    Ok(loaded) => self.loaded(loaded),
+                    Err(err) => self.status = format!("error: {err:?}"),
+                }
+            }
+            if let Some(message) = logged.try_iter().last() {
+                self.status = message;
+            }
+
+            screen
+                .draw(|frame| self.draw(frame))
+                .map_err(Error::from_io)?;
+
+            let ready =
+                tokio::task::block_in_place(|| event::poll(TICK)).map_err(Error::from_io)?;
+            if !ready {
+                continue;
+            }
+            let Event::Key(key) = event::read().map_err(Error::from_io)? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Press && !self.key(key) {
+                return Ok(());
+            }
+        }
+    }
+
+    /* Returns false once the user wants to leave */
+    fn key(&mut self, key: KeyEvent) -> bool {
+        let quitting = std::mem::take(&mut self.quitting);
+
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        if self.focus == Focus::Query {
+            match key.code {
+                KeyCode::Char(c) => self.query.push(c),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                }
+                KeyCode::Enter if !self.query.trim().is_empty() => {
+                    self.status = format!("Searching \"{}\"...", self.query.trim());
+                    self.perform(Action::Search);
+                }
+                KeyCode::Esc | KeyCode::Tab => self.focus = Focus::Results,
+                _ => {}
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => {
+                let unfinished = self.unfinished();
+                if unfinished == 0 || quitting {
+                    return false;
+                }
+                self.status =
+                    format!("{unfinished} downloads pending, press q again to abandon them");
+                self.quitting = true;
+            }
+            KeyCode::Char('/') | KeyCode::Tab => self.focus = Focus::Query,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::PageUp => self.scroll(-10),
+            KeyCode::PageDown => self.scroll(10),
+            KeyCode::Enter if self.current().is_some() => {
+                self.status = "Loading details...".to_owned();
+                self.perform(Action::Details);
+            }
+            KeyCode::Char('s') if self.current().is_some() => {
+                self.status = "Loading series...".to_owned();
+                self.perform(Action::Series);
+            }
+            KeyCode::Esc | KeyCode::Backspace => {
+                if let Some((title, entries)) = self.previous.take() {
+                    self.show(title, entries);
+                }
+            }
+            KeyCode::Char(' ') => self.toggle(),
+            KeyCode::Char('a') => self.toggle_format(BookFormat::AudioBook),
+            KeyCode::Char('e') => self.toggle_format(BookFormat::EBook),
+            KeyCode::Char('d') => self.enqueue(),
+            _ => {}
+        }
+
+        true
+    }
+
+    /* Network calls run in the background to keep the screen responsive */
+    fn perform(&mut self, action: Action) {
+        let client = self.client.clone();
+        let loaded = self.loaded.clone();
+        let preferences = self.preferences;
+        let id = self.current().map(|entry| entry.book.id);
+        let cached = id.and_then(|id| self.details.iter().find(|book| book.id == id).cloned());
+
+        let task = match action {
+            Action::Search => {
+                let query = self.query.trim().to_owned();
+                let languages = preferences.languages.clone();
+                let (kid, includeerotic) = (preferences.kid, preferences.includeerotic());
+                tokio::spawn(async move {
+                    client
+                        .tabsearch_books(&query, 0, PAGE_SIZE, &languages, kid, includeerotic)
+                        .await
+                        .map(|found| Loaded::Results(query, found))
+                })
+            }
+            Action::Details => {
+                let Some(id) = id.filter(|_| cached.is_none()) else {
+                    self.status.clear();
+                    return;
+                };
+                tokio::spawn(async move {
+                    let book = client.books(client::book_id(id)?).await?;
+                    Ok(Loaded::Details(book))
+                })
+            }
+            Action::Series => {
+                let Some(id) = id else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let book = match cached {
+                        Some(book) => book,
+                        None => client.books(client::book_id(id)?).await?,
+                    };
+                    let series = match &book.series {
+                        Some(series) => Some(client.series_all(series.id).await?),
+                        None => None,
+                    };
+                    Ok(Loaded::Series(book, series))
+                })
+            }
+        };
+
+        tokio::spawn(async move {
+            let result = task.await.unwrap_or(Err(Error::Cancelled));
+            let _ = loaded.send(result);
+        });
+    }
+
+    fn loaded(&mut self, loaded: Loaded) {
+        match loaded {
+            Loaded::Results(query, found) => self.results(query, found),
+            Loaded::Details(book) => {
+                self.remember(book);
+                self.status.clear();
+            }
+            Loaded::Series(book, series) => {
+                self.remember(book);
+                match series {
+                    Some(series) => self.open_series(series),
+                    None => self.status = "The book isn't part of a series".to_owned(),
+                }
+            }
+        }
+    }
+
+    fn results(&mut self, query: String, found: Search) {
+        let entries: Vec<Entry> = found
+            ._embedded
+            .books
+            .into_iter()
+            .map(|book| Entry {
+                book,
+                series: None,
+                part: None,
+            })
+            .collect();
+
+        self.status = format!(
+            "{} of {} results for \"{}\"",
+            entries.len(),
+            found.count,
+            query
+        );
+        self.previous = None;
+        self.show(format!("Results for \"{query}\""), entries);
+        self.focus = Focus::Results;
+    }
+
+    /* Full details of books, cached for the session */
+    fn remember(&mut self, book: Book) {
+        if !self.details.iter().any(|known| known.id == book.id) {
+            self.details.push(book);
+        }
+    }
+
+    fn open_series(&mut self, series: Series) {
+        let content = self.preferences.content();
+        let mut skipped = Skipped::default();
+        let entries: Vec<Entry> = series
+            ._embedded
+            .parts
+            .into_iter()
+            .filter(|part| match content.check(&part._embedded.book) {
+                Some(skip) => {
+                    skipped.count(skip);
+                    false
+                }
+                None => true,
+            })
+            .map(|part| Entry {
+                book: part._embedded.book,
+                series: Some(series.name.clone()),
+                part: part.partnumber,
+            })
+            .collect();
+        self.status = if skipped.total() > 0 {
+            format!("Skipped {} parts: {}", skipped.total(), skipped)
+        } else {
+            String::new()
+        };
+
+        /* Only remember the search, not a series opened from a series */
+        let title = format!("{} ({})", series.name, series.count);
+        let previous = std::mem::take(&mut self.entries);
+        if self.previous.is_none() {
+            self.previous = Some((self.title.clone(), previous));
+        }
+        self.show(title, entries);
+    }
+
+    fn show(&mut self, title: String, entries: Vec<Entry>) {
+        self.title = title;
+        self.entries = entries;
+        self.list.select(if self.entries.is_empty() {
+            None
+        } else {
+            Some(0)
+        });
+    }
+
+    fn current(&self) -> Option<&Entry> {
+        self.list
+            .selected()
+            .and_then(|index| self.entries.get(index))
+    }
+
+    fn scroll(&mut self, by: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let index = self.list.selected().unwrap_or(0) as isize + by;
+        let index = index.clamp(0, self.entries.len() as isize - 1);
+        self.list.select(Some(index as usize));
+    }
+
+    fn toggle(&mut self) {
+        if let Some(entry) = self.current().cloned() {
+            self.selected.toggle(&entry, &self.preferences.formats());
+        }
+    }
+
+    fn toggle_format(&mut self, format: BookFormat) {
+        let Some(entry) = self.current().cloned() else {
+            return;
+        };
+        if !self.selected.toggle_format(&entry, format) {
+            self.status = format!("\"{}\" has no {} edition", entry.book.title, name(format));
+        }
+    }
+
+    /* Queue the selection, or the highlighted book when nothing is selected */
+    fn enqueue(&mut self) {
+        if self.selected.is_empty() {
+            self.toggle();
+        }
+
+        let items = self.selected.take(self.client.market());
+        if !items.is_empty() {
+            self.status = format!("Queued {} downloads", items.len());
+        }
+        for item in items {
+            self.queue.push(&item);
+            let _ = self.jobs.send(item);
+        }
+    }
+
+    fn update(&mut self, update: Update) {
+        if let Some(queued) = self.queue.update(update) {
+            if let State::Failed(_) = queued.state {
+                self.status = format!("{} failed", queued.file_name);
+            }
+        }
+    }
+
+    fn unfinished(&self) -> usize {
+        self.queue.unfinished()
+    }
+
+    fn draw<B: Backend>(&mut self, frame: &mut Frame<B>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(5),
+                Constraint::Length(QUEUE_HEIGHT),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rows[1]);
+
+        let focused = |focus: Focus| {
+            if self.focus == focus {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            }
+        };
+
+        let search = Paragraph::new(self.query.as_str()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(focused(Focus::Query))
+                .title(" Search "),
+        );
+        frame.render_widget(search, rows[0]);
+        if self.focus == Focus::Query {
+            let width = self.query.chars().count() as u16;
+            frame.set_cursor(rows[0].x + 1 + width, rows[0].y + 1);
+        }
+
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .map(|entry| ListItem::new(self.line(entry)))
+            .collect();
+        let results = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(focused(Focus::Results))
+                    .title(format!(" {} ", self.title)),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(results, columns[0], &mut self.list);
+
+        let details = Paragraph::new(self.details_text())
+            .block(Block::default().borders(Borders::ALL).title(" Details "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(details, columns[1]);
+
+        /* The latest jobs that fit */
+        let visible = QUEUE_HEIGHT.saturating_sub(2) as usize;
+        let items: Vec<ListItem> = self
+            .queue
+            .items()
+            .iter()
+            .skip(self.queue.len().saturating_sub(visible))
+            .map(|queued| {
+                let state = match &queued.state {
+                    State::Queued => "queued".to_owned(),
+                    State::Running(done, total) => {
+                        format!("{:>5.1}%", *done as f64 * 100.0 / (*total).max(1) as f64)
+                    }
+                    State::Done => "done".to_owned(),
+                    State::Failed(err) => format!("failed: {err}"),
+                    State::Deferred => "deferred".to_owned(),
+                };
+                ListItem::new(format!("{:>7}  {}", state, queued.file_name))
+            })
+            .collect();
+        let done = self.queue.len() - self.unfinished();
+        let queue = List::new(items).block(Block::default().borders(Borders::ALL).title(format!(
+            " Queue {}/{} ",
+            done,
+            self.queue.len()
+        )));
+        frame.render_widget(queue, rows[2]);
+
+        let status = if self.status.is_empty() {
+            Span::styled(HELP, Style::default().fg(Color::DarkGray))
+        } else {
+            Span::raw(self.status.as_str())
+        };
+        frame.render_widget(Paragraph::new(Spans::from(status)), rows[3]);
+    }
+
+    /* Selected formats, whether it was downloaded before, part and title */
+    fn line(&self, entry: &Entry) -> String {
+        let book = &entry.book;
+        let (audiobook, ebook) = self
+            .selected
+            .get(book.id)
+            .map(|selection| (selection.audiobook, selection.ebook))
+            .unwrap_or_default();
+        let downloaded = [&book.audiobookisbn, &book.ebookisbn]
+            .into_iter()
+            .flatten()
+            .any(|isbn| self.downloader.has(isbn));
+
+        let part = entry
+            .part
+            .map(|part| format!("{part}. "))
+            .unwrap_or_default();
+        format!(
+            "[{}{}]{} {}{} - {}",
+            if audiobook { "A" } else { " " },
+            if ebook { "E" } else { " " },
+            if downloaded { "*" } else { " " },
+            part,
+            book.title,
+            book.author
+        )
+    }
+
+    fn details_text(&self) -> Text<'static> {
+        let Some(entry) = self.current() else {
+            return Text::raw("Type a query and press Enter");
+        };
+
+        let Some(book) = self.details.iter().find(|book| book.id == entry.book.id) else {
+            let book = &entry.book;
+            let formats: Vec<&str> = [
+                book.audiobookisbn.as_ref().map(|_| "audiobook"),
+                book.ebookisbn.as_ref().map(|_| "ebook"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+
+            return Text::raw(format!(
+                "{}\nAuthor:    {}\nLanguage:  {}\nPublished: {}\nGrade:     {:.1}\nFormats:   {}\n\nPress Enter for the details",
+                book.title,
+                book.author,
+                book.language,
+                book.published.format("%Y-%m-%d"),
+                book.grade,
+                formats.join(", ")
+            ));
+        };
+
+        let genres: Vec<&str> = book
+            .genres
+            .iter()
+            .map(|genre| genre.name.as_str())
+            .collect();
+        let mut lines = vec![
+            Spans::from(Span::styled(
+                book.title.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(format!("Author:    {}", book.author)),
+            Spans::from(format!("Narrator:  {}", book.narrator)),
+            Spans::from(format!("Language:  {}", book.language)),
+            Spans::from(format!("Published: {}", book.published.format("%Y-%m-%d"))),
+            Spans::from(format!("Grade:     {:.1}", book.grade)),
+            Spans::from(format!("Genres:    {}", genres.join(", "))),
+        ];
+        if let Some(series) = &book.series {
+            let part = series
+                .partnumber
+                .map(|part| format!(", part {part}"))
+                .unwrap_or_default();
+            lines.push(Spans::from(format!(
+                "Series:    {}{} (s)",
+                series.name, part
+            )));
+        }
+        lines.push(Spans::from("Editions:"));
+        for edition in &book.editions {
+            lines.push(Spans::from(format!(
+                "  {} {:9} {} ({})",
+                edition.isbn,
+                name(edition.format),
+                edition.publisher,
+                edition.published.format("%Y-%m-%d")
+            )));
+        }
+        lines.push(Spans::default());
+        lines.push(Spans::from(book.summary.clone()));
+
+        Text::from(lines)
+    }
+}
+
+fn name(format: BookFormat) -> &'static str {
+    match format {
+        BookFormat::AudioBook => "audiobook",
+        BookFormat::EBook => "ebook",
+    }
+}
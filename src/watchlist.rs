@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    api::{Error, Result},
+    client::DateTime,
+    locale::Language,
+};
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Author(String),
+    Narrator(String),
+    Series(u32),
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Author(name) => write!(f, "author \"{name}\""),
+            Self::Narrator(name) => write!(f, "narrator \"{name}\""),
+            Self::Series(id) => write!(f, "series {id}"),
+        }
+    }
+}
+
+/// A followed author, narrator or series.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct Watch {
+    #[serde(flatten)]
+    pub target: Target,
+    /// Falls back to the command line languages when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<Language>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audiobook: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ebook: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<DateTime>,
+}
+
+impl Watch {
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            languages: Vec::new(),
+            audiobook: None,
+            ebook: None,
+            last_sync: None,
+        }
+    }
+}
+
+pub struct Watchlist {
+    path: PathBuf,
+    pub entries: Vec<Watch>,
+}
+
+impl Watchlist {
+    pub fn load(path: &Path) -> Result<Self> {
+        let entries = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(Error::from_serde)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(Error::from_io(err)),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            entries,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let data = serde_json::to_vec_pretty(&self.entries).map_err(Error::from_serde)?;
+
+        /* sync saves after every entry, a crash must not truncate the list */
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, data).map_err(Error::from_io)?;
+        std::fs::rename(&temp, &self.path).map_err(Error::from_io)
+    }
+
+    /// Returns false if the target is already followed.
+    pub fn add(&mut self, watch: Watch) -> bool {
+        if self
+            .entries
+            .iter()
+            .any(|entry| entry.target == watch.target)
+        {
+            return false;
+        }
+        self.entries.push(watch);
+        true
+    }
+
+    pub fn remove(&mut self, target: &Target) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|entry| &entry.target != target);
+        count != self.entries.len()
+    }
+}
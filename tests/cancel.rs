@@ -0,0 +1,69 @@
+use std::{sync::atomic::Ordering, time::Duration};
+
+use bookbeat::{
+    api::Error,
+    client::BookFormat,
+    download::{self, Downloader},
+    throttle::Rate,
+};
+use bookbeat_mock::{MockServer, AUDIOBOOK};
+
+#[tokio::test]
+async fn stopped_downloader_licenses_nothing() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(dir.path().to_owned(), Default::default()).unwrap();
+
+    downloader.cancel().stop();
+    assert!(downloader.cancel().is_stopped());
+    assert!(!downloader.cancel().is_aborted());
+
+    let result = downloader
+        .download(
+            &client,
+            "9780000000011",
+            BookFormat::AudioBook,
+            None,
+            "book.m4a",
+        )
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert_eq!(server.state.licenses.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn aborted_download_leaves_no_file() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    /* Ten seconds for the whole file */
+    let options = download::Options {
+        limit_rate: Some(Rate(AUDIOBOOK.len() as u64 / 10)),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    let cancel = downloader.cancel().clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cancel.stop();
+        cancel.abort();
+    });
+
+    let result = downloader
+        .download(
+            &client,
+            "9780000000011",
+            BookFormat::AudioBook,
+            None,
+            "book.m4a",
+        )
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(!downloader.has("9780000000011"));
+
+    let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+    assert!(files.is_empty(), "{files:?}");
+}
@@ -0,0 +1,88 @@
+use bookbeat::{
+    api::Error,
+    cassette::Cassette,
+    client::{Client, ClientConfig},
+};
+use bookbeat_mock::{MockServer, PASSWORD, USERNAME};
+use std::sync::atomic::Ordering;
+
+#[tokio::test]
+async fn recording_replays_offline() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut config = ClientConfig::with_host(&server.url());
+    config.cassette = Some(Cassette::Record(dir.path().to_owned()));
+    let client = Client::login_with(config, USERNAME, PASSWORD)
+        .await
+        .unwrap();
+    let user = client.users().await.unwrap();
+    let recorded = client.series_all(501).await.unwrap();
+    let license = client.license("9780000000011").await.unwrap();
+    assert!(license._links.download.unwrap().href.contains("/cdn/"));
+
+    let mut files: Vec<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), 5);
+    assert!(files[0].starts_with("0000-get-api-prod-status"));
+
+    for file in &files {
+        let data = std::fs::read_to_string(dir.path().join(file)).unwrap();
+        assert!(!data.contains(PASSWORD));
+        assert!(!data.contains(USERNAME));
+        assert!(!data.contains("mock-token-"));
+        for personal in [
+            &user.email,
+            &user.firstname,
+            &user.lastname,
+            &user.displayname,
+        ] {
+            assert!(!data.contains(&format!("\"{personal}\"")), "{file}");
+        }
+    }
+
+    /* Nothing listens on port 9, every answer has to come from the cassette */
+    let mut config = ClientConfig::with_host("http://127.0.0.1:9");
+    config.cassette = Some(Cassette::Replay(dir.path().to_owned()));
+    let client = Client::login_with(config, "someone", "else").await.unwrap();
+    let replayed = client.series_all(501).await.unwrap();
+    assert_eq!(replayed.name, recorded.name);
+    assert_eq!(replayed._embedded.parts.len(), 2);
+    assert!(client.license("9780000000011").await.is_ok());
+
+    match client.books(1001).await {
+        Err(Error::Cassette(message)) => assert!(message.contains("/api/books/Germany/1001")),
+        other => panic!(
+            "expected missing recording, got {:?}",
+            other.map(|book| book.id)
+        ),
+    }
+
+    assert_eq!(server.state.logins.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn recorded_errors_replay() {
+    let server = MockServer::with_quota(0).await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut config = ClientConfig::with_host(&server.url());
+    config.cassette = Some(Cassette::Record(dir.path().to_owned()));
+    let client = Client::login_with(config, USERNAME, PASSWORD)
+        .await
+        .unwrap();
+    assert!(client.license("9780000000011").await.is_err());
+
+    let mut config = ClientConfig::with_host("http://127.0.0.1:9");
+    config.cassette = Some(Cassette::Replay(dir.path().to_owned()));
+    let client = Client::login_with(config, USERNAME, PASSWORD)
+        .await
+        .unwrap();
+    match client.license("9780000000011").await {
+        Err(Error::Api(429, message)) => assert_eq!(message, "limit exceeded"),
+        other => panic!("expected 429, got {:?}", other.map(|license| license.isbn)),
+    }
+}
@@ -0,0 +1,355 @@
+use bookbeat::{
+    api::Error,
+    availability,
+    client::{self, AuthToken, BookFormat, Client, ClientConfig},
+    locale::{Language, Market},
+    status::{Health, StatusPolicy},
+};
+use bookbeat_mock::{MockServer, PASSWORD, REFRESH_TOKEN, USERNAME};
+use std::sync::atomic::Ordering;
+
+#[tokio::test]
+async fn login_and_fetch_user() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let user = client.users().await.unwrap();
+    assert_eq!(user.email, USERNAME);
+    assert!(user.subscribed());
+    assert_eq!(server.state.logins.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn login_rejects_wrong_password() {
+    let server = MockServer::start().await;
+    let result =
+        Client::login_with(ClientConfig::with_host(&server.url()), USERNAME, "wrong").await;
+
+    assert!(result.is_err());
+    assert_eq!(server.state.logins.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn expired_token_is_refreshed() {
+    let server = MockServer::start().await;
+    let token: AuthToken = serde_json::from_value(serde_json::json!({
+        "refreshtoken": REFRESH_TOKEN,
+        "token": "Bearer mock-token-old",
+        "expiration": "2000-01-01T00:00:00Z",
+    }))
+    .unwrap();
+
+    let client = Client::from_token_with(ClientConfig::with_host(&server.url()), token)
+        .await
+        .unwrap();
+
+    assert_eq!(server.state.refreshes.load(Ordering::SeqCst), 1);
+    assert!(client.users().await.is_ok());
+}
+
+#[tokio::test]
+async fn search_pages_through_results() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let page = client
+        .search(Some("Anna Berg"), None, 0, 2, &[Language::English], true)
+        .await
+        .unwrap();
+    assert_eq!(page.count, 4);
+    assert_eq!(page._embedded.books.len(), 2);
+
+    let all = client
+        .search_all(Some("Anna Berg"), None, &[Language::English], true)
+        .await
+        .unwrap();
+    assert_eq!(all.len(), 4);
+    assert!(all.iter().all(|book| book.language == "English"));
+
+    let narrated = client
+        .search_all(None, Some("Lukas Hahn"), &[], true)
+        .await
+        .unwrap();
+    assert_eq!(narrated.len(), 3);
+}
+
+#[tokio::test]
+async fn tabsearch_matches_titles() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let search = client
+        .tabsearch_books("harbor", 0, 10, &[Language::English], false, true)
+        .await
+        .unwrap();
+    assert_eq!(search.count, 2);
+}
+
+#[tokio::test]
+async fn books_lists_editions() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let book = client.books(1005).await.unwrap();
+    assert_eq!(book.title, "Garden of Salt");
+    let audio = book
+        .editions
+        .iter()
+        .filter(|edition| edition.format == BookFormat::AudioBook)
+        .count();
+    assert_eq!(audio, 2);
+
+    match client.books(9999).await {
+        Err(Error::Api(404, _)) => {}
+        other => panic!("expected 404, got {:?}", other.map(|book| book.id)),
+    }
+}
+
+#[tokio::test]
+async fn series_includes_all_parts() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let series = client.series_all(501).await.unwrap();
+    assert_eq!(series.name, "Harbor Mysteries");
+    let parts: Vec<Option<u32>> = series
+        ._embedded
+        .parts
+        .iter()
+        .map(|part| part.partnumber)
+        .collect();
+    assert_eq!(parts, [Some(1), Some(2)]);
+}
+
+#[tokio::test]
+async fn books_name_their_series() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let series = client.books(1002).await.unwrap().series.unwrap();
+    assert_eq!(series.id, 501);
+    assert_eq!(series.name, "Harbor Mysteries");
+    assert_eq!(series.partnumber, Some(2));
+
+    assert!(client.books(1005).await.unwrap().series.is_none());
+}
+
+#[tokio::test]
+async fn license_is_rate_limited() {
+    let server = MockServer::with_quota(1).await;
+    let client = server.client().await;
+
+    let license = client.license("9780000000011").await.unwrap();
+    assert!(license
+        ._links
+        .download
+        .unwrap()
+        .href
+        .ends_with("/cdn/9780000000011"));
+
+    match client.license("9780000000028").await {
+        Err(Error::Api(429, message)) => assert_eq!(message, "limit exceeded"),
+        other => panic!("expected 429, got {:?}", other.map(|license| license.isbn)),
+    }
+}
+
+#[tokio::test]
+async fn requests_go_through_proxy() {
+    let server = MockServer::start().await;
+
+    /* The host doesn't resolve, only the proxy knows how to answer */
+    let mut config = ClientConfig::with_host("http://api.bookbeat.invalid");
+    config.transport.proxy = Some(server.url());
+    let client = Client::login_with(config, USERNAME, PASSWORD)
+        .await
+        .unwrap();
+
+    assert!(client.users().await.is_ok());
+    assert_eq!(server.state.logins.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn status_policy_decides_login() {
+    let server = MockServer::start().await;
+    *server.state.status.lock().unwrap() = serde_json::json!({
+        "type": "OK",
+        "components": [
+            { "name": "API", "type": "OK" },
+            { "name": "Search", "type": "DEGRADED", "description": "Slow responses" },
+        ],
+        "incidents": [
+            { "title": "Search latency", "status": "investigating", "started": "2030-01-01T10:00:00Z" },
+        ],
+    });
+
+    let config = ClientConfig::with_host(&server.url());
+    let status = Client::service_status(&config).await.unwrap();
+    assert_eq!(status.health(), Health::Degraded);
+    assert_eq!(status.summary(), "OK: Search latency");
+
+    /* A degraded component only warns by default */
+    assert!(Client::login_with(config.clone(), USERNAME, PASSWORD)
+        .await
+        .is_ok());
+
+    let mut strict = config.clone();
+    strict.status_policy = StatusPolicy::Strict;
+    match Client::login_with(strict, USERNAME, PASSWORD).await {
+        Err(Error::Status(summary)) => assert_eq!(summary, "OK: Search latency"),
+        other => panic!("expected status error, got {}", other.is_ok()),
+    }
+
+    *server.state.status.lock().unwrap() = serde_json::json!({ "type": "DOWN" });
+    match Client::login_with(config.clone(), USERNAME, PASSWORD).await {
+        Err(Error::Status(summary)) => assert_eq!(summary, "DOWN"),
+        other => panic!("expected status error, got {}", other.is_ok()),
+    }
+
+    let mut ignore = config;
+    ignore.status_policy = StatusPolicy::Ignore;
+    assert!(Client::login_with(ignore, USERNAME, PASSWORD).await.is_ok());
+}
+
+#[tokio::test]
+async fn unknown_status_only_stops_strict_login() {
+    let server = MockServer::start().await;
+
+    /* Nothing listens there */
+    let mut unreachable = ClientConfig::with_host(&server.url());
+    unreachable.status_host = "http://127.0.0.1:1".to_owned();
+    assert!(Client::service_status(&unreachable).await.is_err());
+
+    /* A status page answering in an unknown format */
+    *server.state.status.lock().unwrap() = serde_json::json!({ "components": "garbage" });
+    let garbled = ClientConfig::with_host(&server.url());
+    assert!(Client::service_status(&garbled).await.is_err());
+
+    for config in [unreachable, garbled] {
+        for policy in [StatusPolicy::AllowDegraded, StatusPolicy::Ignore] {
+            let mut config = config.clone();
+            config.status_policy = policy;
+            assert!(Client::login_with(config, USERNAME, PASSWORD).await.is_ok());
+        }
+
+        let mut strict = config;
+        strict.status_policy = StatusPolicy::Strict;
+        assert!(Client::login_with(strict, USERNAME, PASSWORD)
+            .await
+            .is_err());
+    }
+    assert_eq!(server.state.logins.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn isbn_resolves_to_book() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let book = client.book_by_isbn("9780000000011").await.unwrap().unwrap();
+    assert_eq!(book.id, 1001);
+
+    /* An exact hit is looked up alone */
+    let lookups = |server: &MockServer| {
+        server
+            .state
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with("GET /api/books/"))
+            .count()
+    };
+    assert_eq!(lookups(&server), 1);
+
+    /* Only the first audio edition shows up in search results */
+    let book = client.book_by_isbn("9780000000080").await.unwrap().unwrap();
+    assert_eq!(book.id, 1005);
+
+    assert!(client::book_id(usize::MAX).is_err());
+    assert!(client
+        .book_by_isbn("9789999999999")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn market_is_sent_with_requests() {
+    let server = MockServer::start().await;
+    let mut config = ClientConfig::with_host(&server.url());
+    config.market = "se".parse().unwrap();
+    let client = Client::login_with(config, USERNAME, PASSWORD)
+        .await
+        .unwrap();
+
+    assert_eq!(client.market(), Market::Sweden);
+    client.books(1001).await.unwrap();
+    client
+        .tabsearch_books("harbor", 0, 10, &["en".parse().unwrap()], false, true)
+        .await
+        .unwrap();
+
+    let requests = server.state.requests.lock().unwrap();
+    assert!(requests.contains(&"GET /api/books/Sweden/1001".to_owned()));
+    assert!(requests
+        .iter()
+        .any(|request| request.contains("market=Sweden") && request.contains("language=English")));
+}
+
+#[test]
+fn locale_parsing_accepts_codes_and_rejects_typos() {
+    assert_eq!("germany".parse::<Market>(), Ok(Market::Germany));
+    assert_eq!("DE".parse::<Market>(), Ok(Market::Germany));
+    assert_eq!("english".parse::<Language>(), Ok(Language::English));
+    assert_eq!("sv".parse::<Language>(), Ok(Language::Swedish));
+    assert!("Germnay".parse::<Market>().is_err());
+    assert!("Klingon".parse::<Language>().is_err());
+}
+
+#[tokio::test]
+async fn availability_differs_by_market() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let markets = [Market::Germany, Market::Sweden, Market::Norway];
+    let found = availability::compare(&client, 1005, &markets)
+        .await
+        .unwrap();
+
+    assert!(found[0].has(BookFormat::AudioBook));
+    assert!(!found[0].has(BookFormat::EBook));
+    assert_eq!(found[0].editions(BookFormat::AudioBook).count(), 2);
+    assert!(found[1].has(BookFormat::EBook));
+    assert!(found[2].book.is_none());
+
+    let ebook = availability::pick(&found, BookFormat::EBook).unwrap();
+    assert_eq!(ebook.market, Market::Sweden);
+
+    let requests = server.state.requests.lock().unwrap();
+    assert!(requests.contains(&"GET /api/books/Sweden/1005".to_owned()));
+}
+
+#[tokio::test]
+async fn genres_list_and_filter_books() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let genres = client.genres().await.unwrap();
+    let names: Vec<&str> = genres.iter().map(|genre| genre.name.as_str()).collect();
+    assert_eq!(names, ["Crime", "Fiction", "Food", "Romance"]);
+
+    let crime = client
+        .genre_books_all(10, &[Language::English], true)
+        .await
+        .unwrap();
+    let ids: Vec<usize> = crime.iter().map(|book| book.id).collect();
+    assert_eq!(ids, [1002, 1001]);
+
+    let page = client.genre_books(10, 0, 1, &[], true).await.unwrap();
+    assert_eq!(page.count, 3);
+    assert_eq!(page._embedded.books.len(), 1);
+
+    /* Erotic books are left out for --sfw */
+    let romance = client.genre_books_all(11, &[], false).await.unwrap();
+    assert!(romance.is_empty());
+}
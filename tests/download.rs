@@ -0,0 +1,179 @@
+use std::sync::{atomic::Ordering, Arc, Mutex};
+
+use bookbeat::{
+    api::Error,
+    client::BookFormat,
+    cover::CoverFormat,
+    download::{self, Downloader, Metadata},
+    library::Library,
+};
+use bookbeat_mock::{MockServer, AUDIOBOOK, EBOOK};
+
+#[tokio::test]
+async fn audiobook_is_tagged_and_decorated() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let options = download::Options {
+        book_dirs: true,
+        cover_sidecar: true,
+        metadata_sidecar: true,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    let series = client.series_all(501).await.unwrap();
+    let part = &series._embedded.parts[0];
+    let book = &part._embedded.book;
+    let metadata = Metadata::from(book).with_series(&series.name, part.partnumber);
+    let isbn = book.audiobookisbn.as_deref().unwrap();
+
+    let download = downloader
+        .download(
+            &client,
+            isbn,
+            BookFormat::AudioBook,
+            Some(&metadata),
+            "book.m4a",
+        )
+        .await
+        .unwrap();
+
+    let folder = dir.path().join("Anna Berg").join("The Silent Harbor");
+    assert_eq!(download.path, folder.join("book.m4a"));
+    assert_eq!(download.series.as_deref(), Some("Harbor Mysteries"));
+
+    let tag = mp4ameta::Tag::read_from_path(&download.path).unwrap();
+    assert_eq!(tag.title(), Some("The Silent Harbor"));
+    assert_eq!(tag.artist(), Some("Anna Berg"));
+    assert!(tag.artwork().is_some());
+
+    let sidecar = std::fs::read(folder.join("cover.jpg")).unwrap();
+    assert_eq!(CoverFormat::detect(&sidecar), Some(CoverFormat::Jpeg));
+    let abs: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(folder.join("metadata.json")).unwrap()).unwrap();
+    assert_eq!(abs["series"][0], "Harbor Mysteries #1");
+    assert_eq!(abs["narrators"][0], "Lukas Hahn");
+    assert!(folder.join("metadata.opf").exists());
+
+    assert!(downloader.has(isbn));
+    let library = Library::load(dir.path()).unwrap();
+    assert_eq!(library.entries().len(), 1);
+    assert_eq!(library.entries()[0].part, Some(1));
+}
+
+#[tokio::test]
+async fn bare_isbn_is_stored_flat() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let options = download::Options {
+        book_dirs: true,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    let download = downloader
+        .download(
+            &client,
+            "9780000000028",
+            BookFormat::EBook,
+            None,
+            "a/b.epub",
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(download.path, dir.path().join("a_b.epub"));
+    assert_eq!(std::fs::read(&download.path).unwrap(), EBOOK);
+    assert_eq!(download.size, EBOOK.len() as u64);
+}
+
+#[tokio::test]
+async fn progress_is_reported_to_callback() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    let on_progress: download::OnProgress = Arc::new(move |isbn: &str, done, total| {
+        sink.lock().unwrap().push((isbn.to_owned(), done, total));
+    });
+    let options = download::Options {
+        on_progress: Some(on_progress),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    /* Full details name the series, just like a series listing */
+    let book = client.books(1002).await.unwrap();
+    let metadata = Metadata::from(&book);
+    let download = downloader
+        .download(
+            &client,
+            "9780000000035",
+            BookFormat::AudioBook,
+            Some(&metadata),
+            "book.m4a",
+        )
+        .await
+        .unwrap();
+    assert_eq!(download.series.as_deref(), Some("Harbor Mysteries"));
+    assert_eq!(download.part, Some(2));
+
+    let reports = reports.lock().unwrap();
+    let (isbn, done, _) = reports.last().unwrap();
+    assert_eq!(isbn, "9780000000035");
+    assert_eq!(*done, AUDIOBOOK.len() as u64);
+}
+
+#[tokio::test]
+async fn short_transfer_is_not_kept() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(dir.path().to_owned(), Default::default()).unwrap();
+
+    server.state.truncate.store(3, Ordering::SeqCst);
+    let result = downloader
+        .download(
+            &client,
+            "9780000000028",
+            BookFormat::EBook,
+            None,
+            "book.epub",
+        )
+        .await;
+    let total = EBOOK.len() as u64;
+    assert!(
+        matches!(result, Err(Error::Truncated(size, expected)) if size == total - 3 && expected == total)
+    );
+
+    assert!(!downloader.has("9780000000028"));
+    let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+    assert!(files.is_empty(), "{files:?}");
+}
+
+#[tokio::test]
+async fn license_without_download_link_fails() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(dir.path().to_owned(), Default::default()).unwrap();
+
+    server.state.stream_only.store(true, Ordering::SeqCst);
+    let result = downloader
+        .download(
+            &client,
+            "9780000000028",
+            BookFormat::EBook,
+            None,
+            "book.epub",
+        )
+        .await;
+    assert!(matches!(result, Err(Error::NoDownload(isbn)) if isbn == "9780000000028"));
+    assert!(!downloader.has("9780000000028"));
+}
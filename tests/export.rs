@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+
+use bookbeat::{
+    client::BookFormat,
+    export::{self, ExportFormat},
+    library::{Entry, Library},
+};
+
+fn library(root: &std::path::Path) -> Library {
+    let mut library = Library::load(root).unwrap();
+    let downloaded = "2024-05-01T12:00:00Z".parse().unwrap();
+    let audiobook = Entry {
+        id: Some(1001),
+        isbn: "9780000000011".to_owned(),
+        format: BookFormat::AudioBook,
+        title: Some("Harbor, Silent".to_owned()),
+        author: Some("Anna Berg, Erik Berg".to_owned()),
+        narrator: Some("Lena \"Lee\" Holm".to_owned()),
+        series: Some("Harbor".to_owned()),
+        part: Some(1),
+        path: root.join("Harbor, Silent.m4a"),
+        size: 1024,
+        downloaded,
+        published: Some("2021-03-01T00:00:00Z".parse().unwrap()),
+        language: Some("en".to_owned()),
+        genres: vec!["Crime".to_owned(), "Thriller".to_owned()],
+    };
+    let ebook = Entry {
+        isbn: "9780000000028".to_owned(),
+        format: BookFormat::EBook,
+        narrator: None,
+        path: PathBuf::from("Harbor, Silent.epub"),
+        size: 512,
+        ..audiobook.clone()
+    };
+    library.insert(audiobook);
+    library.insert(ebook);
+    library
+}
+
+#[test]
+fn csv_quotes_fields_and_pairs_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv = export::export(&library(dir.path()), ExportFormat::Csv).unwrap();
+    let lines: Vec<&str> = csv.split("\r\n").collect();
+
+    assert!(lines[0].starts_with("title,author,narrator,series,part,isbn,"));
+    let path = dir.path().join("Harbor, Silent.m4a");
+    assert_eq!(
+        lines[1],
+        format!(
+            "\"Harbor, Silent\",\"Anna Berg, Erik Berg\",\"Lena \"\"Lee\"\" Holm\",Harbor,1,\
+             9780000000011,1001,audiobook,\"{}\",1024,2024-05-01T12:00:00+00:00,2021-03-01,en,\
+             Crime;Thriller,9780000000028",
+            path.display()
+        )
+    );
+    assert!(lines[2].ends_with(",9780000000011"));
+    assert_eq!(lines[3], "");
+}
+
+#[test]
+fn json_holds_absolute_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = export::export(&library(dir.path()), ExportFormat::Json).unwrap();
+    let entries: Vec<Entry> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].isbn, "9780000000011");
+    assert_eq!(entries[1].path, dir.path().join("Harbor, Silent.epub"));
+    assert_eq!(entries[1].format, BookFormat::EBook);
+}
+
+#[test]
+fn calibre_lists_format_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv = export::export(&library(dir.path()), ExportFormat::Calibre).unwrap();
+    let lines: Vec<&str> = csv.split("\r\n").collect();
+
+    assert_eq!(
+        lines[0],
+        "title,authors,series,series_index,identifiers,pubdate,languages,tags,formats,comments"
+    );
+    assert_eq!(
+        lines[1],
+        "\"Harbor, Silent\",Anna Berg & Erik Berg,Harbor,1,\"isbn:9780000000011,bookbeat:1001\",\
+         2021-03-01,en,\"Crime,Thriller\",M4A,\"Narrated by Lena \"\"Lee\"\" Holm\""
+    );
+    assert!(lines[2].contains(",EPUB,"), "{}", lines[2]);
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use bookbeat::{
+    feed::{self, Feed},
+    locale::Language,
+};
+use bookbeat_mock::MockServer;
+use tokio::net::{TcpListener, TcpStream};
+
+#[tokio::test]
+async fn feed_is_served_while_a_client_idles() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let (atom, cached) = tokio::sync::watch::channel(None);
+    tokio::spawn(feed::serve(listener, cached));
+
+    /* Nothing built yet */
+    let http = reqwest::Client::new();
+    let response = http.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), 502);
+
+    let books = client
+        .search_all(Some("Anna Berg"), None, &[Language::English], true)
+        .await
+        .unwrap();
+    let mut feed = Feed::new("New releases".to_owned());
+    for book in &books {
+        feed.push(feed::Entry::new(book, "author \"Anna Berg\"".to_owned()));
+    }
+    atom.send_replace(Some(feed.to_atom()));
+
+    /* A connection that never sends a request doesn't hold up the others */
+    let _idle = TcpStream::connect(listener_addr(&url)).await.unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(2), http.get(&url).send())
+        .await
+        .expect("blocked by the idle connection")
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<title>The Silent Harbor</title>"), "{body}");
+}
+
+fn listener_addr(url: &str) -> &str {
+    url.trim_start_matches("http://").trim_end_matches('/')
+}
@@ -0,0 +1,168 @@
+use bookbeat::{
+    client::{BookFormat, SearchBook},
+    filter::{Content, Filter, Skip, Skipped},
+    locale::Language,
+    watchlist::Watchlist,
+};
+use bookbeat_mock::MockServer;
+use chrono::NaiveDate;
+use regex::Regex;
+
+async fn anna_berg() -> Vec<SearchBook> {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    client
+        .search_all(Some("Anna Berg"), None, &[Language::English], true)
+        .await
+        .unwrap()
+}
+
+fn ids(filter: &Filter, books: &[SearchBook], author: Option<&str>) -> Vec<usize> {
+    let mut ids: Vec<usize> = filter
+        .apply(books, |book| book, author)
+        .iter()
+        .map(|book| book.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn exact_author_drops_coauthors_and_similar_names() {
+    let books = anna_berg().await;
+    assert_eq!(ids(&Filter::default(), &books, None), [1001, 1002, 1003, 1006]);
+
+    let filter = Filter {
+        exact_author: true,
+        ..Default::default()
+    };
+    assert_eq!(ids(&filter, &books, Some("anna berg")), [1001, 1002]);
+    assert_eq!(ids(&filter, &books, None).len(), 4);
+}
+
+#[tokio::test]
+async fn conditions_combine() {
+    let books = anna_berg().await;
+
+    let filter = Filter {
+        min_grade: Some(4.0),
+        since: NaiveDate::from_ymd_opt(2021, 1, 1),
+        until: NaiveDate::from_ymd_opt(2022, 12, 31),
+        ..Default::default()
+    };
+    assert_eq!(ids(&filter, &books, None), [1001, 1002, 1006]);
+
+    let filter = Filter {
+        include: Some(Regex::new("(?i)harbor").unwrap()),
+        exclude: Some(Regex::new("Tides").unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(ids(&filter, &books, None), [1001]);
+
+    let filter = Filter {
+        formats: vec![BookFormat::AudioBook, BookFormat::EBook],
+        ..Default::default()
+    };
+    assert_eq!(ids(&filter, &books, None), [1001, 1003]);
+
+    let filter = Filter {
+        formats: vec![BookFormat::AudioBook],
+        limit: Some(2),
+        ..Default::default()
+    };
+    let kept = filter.apply(&books, |book| book, None);
+    assert_eq!(kept.len(), 2);
+    assert!(kept.iter().all(|book| book.audiobookisbn.is_some()));
+}
+
+#[tokio::test]
+async fn content_settings_apply_to_series_and_ids() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let content = Content {
+        languages: vec![Language::English],
+        sfw: true,
+    };
+
+    /* Mixed languages and an explicit part */
+    let series = client.series_all(502).await.unwrap();
+    let mut skipped = Skipped::default();
+    let mut kept = Vec::new();
+    for part in &series._embedded.parts {
+        match content.check(&part._embedded.book) {
+            Some(skip) => skipped.count(skip),
+            None => kept.push(part._embedded.book.id),
+        }
+    }
+    assert_eq!(kept, [1006]);
+    assert_eq!(skipped.total(), 2);
+    assert_eq!(skipped.to_string(), "1 in other languages, 1 explicit");
+
+    /* Books asked for directly are only checked for explicit content */
+    let explicit = client.books(1003).await.unwrap();
+    assert_eq!(content.check_book(&explicit), Some(Skip::Explicit));
+    let german = client.books(1004).await.unwrap();
+    assert_eq!(content.check_book(&german), None);
+
+    let everything = Content::default();
+    assert_eq!(
+        everything.check(&series._embedded.parts[1]._embedded.book),
+        None
+    );
+}
+
+#[tokio::test]
+async fn languages_outside_the_list_are_kept_by_name() {
+    let mut book = anna_berg().await.remove(0);
+    book.language = "Ukrainian".to_owned();
+
+    let ukrainian = Language::named("ukrainian");
+    assert_eq!(ukrainian, Language::Other("ukrainian".to_owned()));
+    assert_eq!(Language::named("en"), Language::English);
+
+    let content = Content {
+        languages: vec![ukrainian],
+        sfw: false,
+    };
+    assert_eq!(content.check(&book), None);
+
+    let content = Content {
+        languages: vec![Language::English],
+        sfw: false,
+    };
+    assert_eq!(content.check(&book), Some(Skip::Language));
+
+    /* One unknown language doesn't break the watchlist */
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("watchlist.json");
+    std::fs::write(
+        &path,
+        r#"[{"author": "Anna Berg", "languages": ["English", "Klingon"]}]"#,
+    )
+    .unwrap();
+    let watchlist = Watchlist::load(&path).unwrap();
+    assert_eq!(
+        watchlist.entries[0].languages,
+        [Language::English, Language::Other("Klingon".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn books_without_the_explicit_flag_are_kept() {
+    let mut book = anna_berg().await.remove(0);
+    book.erotic = None;
+
+    let content = Content {
+        languages: Vec::new(),
+        sfw: true,
+    };
+    assert_eq!(content.check(&book), None);
+    assert!(content.unsure(book.erotic));
+    assert!(!Content::default().unsure(book.erotic));
+
+    book.erotic = Some(true);
+    assert_eq!(content.check(&book), Some(Skip::Explicit));
+    assert!(!content.unsure(book.erotic));
+}
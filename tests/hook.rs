@@ -0,0 +1,127 @@
+#![cfg(unix)]
+
+use bookbeat::{
+    api::Error,
+    client::BookFormat,
+    download::{self, Batch, Downloader},
+    hook::Hook,
+    locale::Market,
+    plan::Plan,
+};
+use bookbeat_mock::{MockServer, AUDIOBOOK};
+
+#[tokio::test]
+async fn hooks_get_the_download_as_env_and_json() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let events = tempfile::tempdir().unwrap();
+    let env = events.path().join("env");
+    let json = events.path().join("download.json");
+    let batch = events.path().join("batch.json");
+    let count = events.path().join("count");
+
+    let options = download::Options {
+        on_download: Some(Hook::new(format!(
+            "env > '{}'; cat > '{}'",
+            env.display(),
+            json.display()
+        ))),
+        on_finish: Some(Hook::new(format!(
+            "echo $BOOKBEAT_COUNT > '{}'; cat > '{}'",
+            count.display(),
+            batch.display()
+        ))),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    let series = client.series_all(501).await.unwrap();
+    let mut plan = Plan::default();
+    plan.add_part(
+        &series,
+        &series._embedded.parts[1],
+        &[BookFormat::AudioBook],
+        Market::Germany,
+    );
+    let mut done = Batch::default();
+    downloader
+        .download_all(&client, plan.items(), &mut done)
+        .await
+        .unwrap();
+    downloader.finish_batch().await;
+
+    let env = std::fs::read_to_string(env).unwrap();
+    let path = dir
+        .path()
+        .join("002 The Silent Harbor: Tides (9780000000035).m4a");
+    for var in [
+        "BOOKBEAT_ISBN=9780000000035".to_owned(),
+        format!("BOOKBEAT_PATH={}", path.display()),
+        "BOOKBEAT_FORMAT=audiobook".to_owned(),
+        format!("BOOKBEAT_SIZE={}", AUDIOBOOK.len()),
+        "BOOKBEAT_ID=1002".to_owned(),
+        "BOOKBEAT_TITLE=The Silent Harbor: Tides".to_owned(),
+        "BOOKBEAT_AUTHOR=Anna Berg".to_owned(),
+        "BOOKBEAT_SERIES=Harbor Mysteries".to_owned(),
+        "BOOKBEAT_PART=2".to_owned(),
+    ] {
+        assert!(env.lines().any(|line| line == var), "{var} missing");
+    }
+
+    let download: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(json).unwrap()).unwrap();
+    assert_eq!(download["isbn"], "9780000000035");
+    assert_eq!(download["path"], path.display().to_string());
+    assert_eq!(download["part"], 2);
+    assert_eq!(download["size"], AUDIOBOOK.len());
+
+    let finished: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&batch).unwrap()).unwrap();
+    assert_eq!(finished["count"], 1);
+    assert_eq!(finished["downloads"][0]["isbn"], "9780000000035");
+    assert_eq!(std::fs::read_to_string(count).unwrap().trim(), "1");
+}
+
+#[tokio::test]
+async fn failing_hook_is_reported_and_the_batch_goes_on() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let events = tempfile::tempdir().unwrap();
+    let batch = events.path().join("batch.json");
+
+    let failing = Hook::new("cat > /dev/null; exit 3".to_owned());
+    let options = download::Options {
+        on_download: Some(failing.clone()),
+        on_finish: Some(Hook::new(format!("cat > '{}'", batch.display()))),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    let series = client.series_all(501).await.unwrap();
+    let mut plan = Plan::default();
+    for part in &series._embedded.parts {
+        plan.add_part(&series, part, &[BookFormat::AudioBook], Market::Germany);
+    }
+    let mut done = Batch::default();
+    downloader
+        .download_all(&client, plan.items(), &mut done)
+        .await
+        .unwrap();
+    downloader.finish_batch().await;
+
+    /* Both files made it despite the hook failing after each */
+    assert_eq!(done.completed.len(), 2);
+    assert!(done.stopped.is_none());
+    assert!(downloader.has("9780000000011"));
+    assert!(downloader.has("9780000000035"));
+    let finished: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&batch).unwrap()).unwrap();
+    assert_eq!(finished["count"], 2);
+
+    match failing.downloaded(&done.completed[0]).await {
+        Err(Error::Hook(message)) => assert!(message.ends_with("exit status: 3"), "{message}"),
+        other => panic!("expected hook error, got {other:?}"),
+    }
+}
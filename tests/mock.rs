@@ -0,0 +1,106 @@
+use bookbeat::client::{Book, License, Search, Series, User};
+use bookbeat_mock::{MockServer, AUDIOBOOK, PASSWORD, RATE_LIMIT_RESET, USERNAME};
+use serde_json::{json, Value};
+
+async fn login(http: &reqwest::Client, server: &MockServer) -> String {
+    let login: Value = http
+        .post(format!("{}/api/login", server.url()))
+        .json(&json!({ "username": USERNAME, "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    format!("Bearer {}", login["token"].as_str().unwrap())
+}
+
+async fn get(http: &reqwest::Client, token: &str, url: String) -> reqwest::Response {
+    http.get(url)
+        .header("authorization", token)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn responses_decode_into_client_models() {
+    let server = MockServer::start().await;
+    let http = reqwest::Client::new();
+    let token = login(&http, &server).await;
+    let url = server.url();
+
+    let user: User = get(&http, &token, format!("{url}/api/users"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(user.email, USERNAME);
+
+    let search: Search = get(
+        &http,
+        &token,
+        format!("{url}/api/search/books?author=Anna%20Berg&offset=0&limit=2"),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    assert!(search.count > 2);
+    assert_eq!(search._embedded.books.len(), 2);
+
+    let book: Book = get(&http, &token, format!("{url}/api/books/Germany/1001"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(book.title, "The Silent Harbor");
+
+    let series: Series = get(&http, &token, format!("{url}/api/series/501"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(series._embedded.parts[0]._embedded.book.id, 1001);
+}
+
+#[tokio::test]
+async fn requests_need_a_token() {
+    let server = MockServer::start().await;
+    let http = reqwest::Client::new();
+
+    let response = get(
+        &http,
+        "Bearer stolen",
+        format!("{}/api/users", server.url()),
+    )
+    .await;
+    assert_eq!(response.status(), 401);
+
+    let response = http
+        .post(format!("{}/api/login", server.url()))
+        .json(&json!({ "username": USERNAME, "password": "wrong" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn license_links_to_the_cdn_until_the_quota_is_used_up() {
+    let server = MockServer::with_quota(1).await;
+    let http = reqwest::Client::new();
+    let token = login(&http, &server).await;
+    let url = format!("{}/api/content/9780000000011/license", server.url());
+
+    let license: License = get(&http, &token, url.clone()).await.json().await.unwrap();
+    assert_eq!(license.filesize, AUDIOBOOK.len());
+    let href = &license._links.download.unwrap().href;
+    let file = http.get(href).send().await.unwrap().bytes().await.unwrap();
+    assert_eq!(&file[..], AUDIOBOOK);
+
+    let response = get(&http, &token, url).await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["x-rate-limit-remaining"], "0");
+    assert_eq!(response.headers()["x-rate-limit-reset"], RATE_LIMIT_RESET);
+}
@@ -0,0 +1,178 @@
+use bookbeat::{
+    client::BookFormat,
+    locale::{Language, Market},
+    plan::{EditionPolicy, Plan},
+};
+use bookbeat_mock::MockServer;
+
+#[tokio::test]
+async fn selectors_are_merged_into_one_item_per_file() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let formats = [BookFormat::AudioBook];
+    let mut plan = Plan::default();
+
+    /* --id 1001 --author "Anna Berg" --series 501 */
+    let book = client.books(1001).await.unwrap();
+    plan.add_book(&book, BookFormat::AudioBook, Market::Germany);
+
+    let books = client
+        .search_all(Some("Anna Berg"), None, &[Language::English], true)
+        .await
+        .unwrap();
+    for book in &books {
+        plan.add_search(book, &formats, Market::Germany);
+    }
+
+    let series = client.series_all(501).await.unwrap();
+    for part in &series._embedded.parts {
+        plan.add_part(&series, part, &formats, Market::Germany);
+    }
+
+    let isbns: Vec<&str> = plan.items().iter().map(|item| item.isbn.as_str()).collect();
+    assert_eq!(
+        isbns,
+        ["9780000000011", "9780000000035", "9780000000042"],
+        "1001 from the id first, then the other audiobooks of the author"
+    );
+    assert_eq!(plan.duplicates(), 3);
+
+    /* Full details from the id, part number from the series */
+    let first = &plan.items()[0];
+    assert!(first.book.is_some());
+    assert!(first.search.is_some());
+    assert_eq!(first.series.as_deref(), Some("Harbor Mysteries"));
+    assert_eq!(
+        first.file_name(),
+        "001 The Silent Harbor (9780000000011).m4a"
+    );
+    let metadata = first.metadata().unwrap();
+    assert_eq!(metadata.part, Some(1));
+    assert!(metadata.book.is_some());
+
+    /* A search hit that no series claimed keeps its plain name */
+    let night_trains = plan
+        .items()
+        .iter()
+        .find(|item| item.title() == Some("Night Trains"))
+        .unwrap();
+    assert!(night_trains.file_name().starts_with("Night Trains ("));
+}
+
+#[tokio::test]
+async fn edition_from_the_id_replaces_the_one_a_search_names() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let formats = [BookFormat::AudioBook];
+
+    /* --id 1005 --author "Marta Lind", the search names the 2019 edition */
+    let book = client.books(1005).await.unwrap();
+    let books = client
+        .search_all(Some("Marta Lind"), None, &[Language::English], true)
+        .await
+        .unwrap();
+    assert_eq!(books[0].audiobookisbn.as_deref(), Some("9780000000073"));
+
+    let mut plan = Plan::default();
+    plan.add_book(&book, BookFormat::AudioBook, Market::Germany);
+    for book in &books {
+        plan.add_search(book, &formats, Market::Germany);
+    }
+    let isbns: Vec<&str> = plan.items().iter().map(|item| item.isbn.as_str()).collect();
+    assert_eq!(isbns, ["9780000000080"]);
+    assert_eq!(plan.duplicates(), 1);
+    assert!(plan.items()[0].search.is_some());
+
+    /* The same the other way around */
+    let mut plan = Plan::default();
+    for book in &books {
+        plan.add_search(book, &formats, Market::Germany);
+    }
+    plan.add_book(&book, BookFormat::AudioBook, Market::Germany);
+    let isbns: Vec<&str> = plan.items().iter().map(|item| item.isbn.as_str()).collect();
+    assert_eq!(isbns, ["9780000000080"]);
+    assert_eq!(plan.duplicates(), 1);
+
+    /* Every edition asked for is still planned */
+    let mut plan = Plan::new(EditionPolicy::All);
+    plan.add_book(&book, BookFormat::AudioBook, Market::Germany);
+    for book in &books {
+        plan.add_search(book, &formats, Market::Germany);
+    }
+    assert_eq!(plan.len(), 2);
+    assert_eq!(plan.duplicates(), 1);
+}
+
+#[tokio::test]
+async fn edition_policy_picks_for_listed_books_too() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let formats = [BookFormat::AudioBook, BookFormat::EBook];
+
+    /* --author "Marta Lind" names the 2019 audiobook */
+    let books = client
+        .search_all(Some("Marta Lind"), None, &[Language::English], true)
+        .await
+        .unwrap();
+    let picked = |policy: &str| {
+        let mut plan = Plan::new(policy.parse().unwrap());
+        for book in &books {
+            plan.add_search(book, &formats, Market::Germany);
+        }
+        assert_eq!(plan.unresolved(), [1005]);
+        plan
+    };
+
+    let mut plan = picked("newest");
+    let book = client.books(1005).await.unwrap();
+    plan.add_details(&book);
+    assert!(plan.unresolved().is_empty());
+    let isbns: Vec<&str> = plan.items().iter().map(|item| item.isbn.as_str()).collect();
+    assert_eq!(isbns, ["9780000000080", "9780000000097"]);
+    assert!(plan.items().iter().all(|item| item.pinned));
+    assert_eq!(plan.items()[0].editions().len(), 2);
+
+    let mut plan = picked("publisher:Coast Audio");
+    plan.add_details(&book);
+    assert_eq!(plan.items()[0].isbn, "9780000000073");
+
+    let mut plan = picked("all");
+    plan.add_details(&book);
+    assert_eq!(plan.len(), 3);
+    assert_eq!(plan.duplicates(), 0);
+
+    /* The search hit of the --id book only leaves its ebook to resolve */
+    let mut plan = Plan::default();
+    plan.add_book(&book, BookFormat::AudioBook, Market::Germany);
+    plan.add_search(&books[0], &formats, Market::Germany);
+    assert_eq!(plan.unresolved(), [1005]);
+    plan.add_details(&book);
+    let isbns: Vec<&str> = plan.items().iter().map(|item| item.isbn.as_str()).collect();
+    assert_eq!(isbns, ["9780000000080", "9780000000097"]);
+}
+
+#[tokio::test]
+async fn edition_policy_picks_one_edition_per_format() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    /* Two audiobooks, Coast Audio from 2019 and Abridged House from 2021 */
+    let book = client.books(1005).await.unwrap();
+    let picked = |policy: &str| {
+        let mut plan = Plan::new(policy.parse().unwrap());
+        plan.add_book(&book, BookFormat::AudioBook, Market::Germany);
+        plan.items()
+            .iter()
+            .map(|item| item.isbn.clone())
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(picked("newest"), ["9780000000080"]);
+    assert_eq!(picked("oldest"), ["9780000000073"]);
+    assert_eq!(picked("publisher:coast audio"), ["9780000000073"]);
+    assert_eq!(picked("publisher:Nobody"), ["9780000000080"]);
+    assert_eq!(picked("isbn:9780000000073"), ["9780000000073"]);
+    assert_eq!(picked("all"), ["9780000000073", "9780000000080"]);
+
+    assert!("cheapest".parse::<EditionPolicy>().is_err());
+}
@@ -0,0 +1,89 @@
+use bookbeat::{
+    api::Error,
+    client::{BookFormat, DateTime},
+    locale::Market,
+    plan::Plan,
+    queue::Queue,
+};
+use bookbeat_mock::{MockServer, RATE_LIMIT_RESET};
+
+#[tokio::test]
+async fn quota_reset_is_remembered() {
+    let server = MockServer::with_quota(0).await;
+    let client = server.client().await;
+    assert_eq!(client.quota_reset(), None);
+
+    let err = client.license("9780000000011").await.unwrap_err();
+    assert!(err.is_quota_exceeded());
+
+    let reset: DateTime = RATE_LIMIT_RESET.parse().unwrap();
+    assert_eq!(client.quota_reset(), Some(reset));
+    assert_eq!(client.for_market(Market::Sweden).quota_reset(), Some(reset));
+
+    /* Other endpoints may be rate limited without the quota being used up */
+    assert!(!Error::Api(429, "Too many requests".to_owned()).is_quota_exceeded());
+}
+
+#[test]
+fn queue_waits_for_the_next_future_reset() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = Queue::load(dir.path()).unwrap();
+    let now: DateTime = "2030-01-01T00:00:00Z".parse().unwrap();
+    let minutes = |minutes| now + chrono::Duration::minutes(minutes);
+
+    queue.limit_reached(Some(minutes(20)), now);
+    assert_eq!(queue.reset, Some(minutes(20)));
+    assert_eq!(queue.until_reset(now).as_secs(), 20 * 60);
+
+    queue.limit_reached(Some(minutes(-5)), now);
+    assert_eq!(queue.reset, Some(minutes(60)));
+
+    queue.limit_reached(None, now);
+    assert_eq!(queue.reset, Some(minutes(60)));
+}
+
+#[tokio::test]
+async fn queue_survives_a_restart() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut plan = Plan::default();
+    let series = client.series_all(501).await.unwrap();
+    for part in &series._embedded.parts {
+        plan.add_part(&series, part, &[BookFormat::AudioBook], Market::Germany);
+    }
+    let names: Vec<String> = plan.items().iter().map(|item| item.file_name()).collect();
+
+    let mut queue = Queue::load(dir.path()).unwrap();
+    for item in plan.items() {
+        assert!(queue.push(item.clone()));
+    }
+    assert!(!queue.push(plan.items()[0].clone()));
+    queue.reset = Some(RATE_LIMIT_RESET.parse().unwrap());
+    queue.save().unwrap();
+
+    /* Named and tagged the same after loading */
+    let mut queue = Queue::load(dir.path()).unwrap();
+    let loaded: Vec<String> = queue.items().iter().map(|item| item.file_name()).collect();
+    assert_eq!(loaded, names);
+    assert_eq!(queue.items()[0].metadata().unwrap().part, Some(1));
+    assert!(!queue.until_reset(chrono::Utc::now()).is_zero());
+
+    /* By position or ISBN */
+    let last = queue.items()[names.len() - 1].isbn.clone();
+    assert_eq!(queue.find("1"), Some(0));
+    assert_eq!(queue.find(&last), Some(names.len() - 1));
+    assert_eq!(queue.find("9999999999999"), None);
+
+    queue.reorder(names.len() - 1, 0);
+    assert_eq!(queue.items()[0].isbn, last);
+    queue.reorder(0, 99);
+    assert_eq!(queue.items()[names.len() - 1].isbn, last);
+
+    while !queue.is_empty() {
+        queue.remove(0);
+    }
+    queue.save().unwrap();
+    assert!(!dir.path().join("queue.json").exists());
+}
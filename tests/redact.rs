@@ -0,0 +1,47 @@
+use bookbeat::redact;
+use reqwest::header::HeaderValue;
+
+#[test]
+fn body_hides_credentials_and_personal_data() {
+    let body = br#"{
+        "email": "reader@example.com",
+        "firstname": "Mock",
+        "lastname": "Reader",
+        "displayname": "Mock Reader",
+        "userid": 4242,
+        "token": "mock-token-1",
+        "_embedded": {"sessions": [{"refreshtoken": "mock-refresh"}]},
+        "_links": {"download": {"href": "https://cdn.example.com/book.m4a?signature=abc"}}
+    }"#;
+    let printed = redact::body(body);
+
+    for secret in [
+        "reader@example.com",
+        "\"Mock\"",
+        "\"Reader\"",
+        "Mock Reader",
+        "mock-token-1",
+        "mock-refresh",
+        "signature",
+    ] {
+        assert!(!printed.contains(secret), "{secret} in {printed}");
+    }
+    assert!(printed.contains("4242"));
+    assert!(printed.contains("https://cdn.example.com/book.m4a"));
+    assert!(printed.contains(redact::REDACTED));
+}
+
+#[test]
+fn body_that_is_not_json_is_only_measured() {
+    assert_eq!(redact::body(b"password=secret"), "(15 bytes)");
+}
+
+#[test]
+fn credentials_headers_are_hidden() {
+    let value = HeaderValue::from_static("Bearer mock-token-1");
+    assert_eq!(redact::header("Authorization", &value), redact::REDACTED);
+    assert_eq!(
+        redact::header("x-rate-limit-remaining", &value),
+        "Bearer mock-token-1"
+    );
+}
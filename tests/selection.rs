@@ -0,0 +1,169 @@
+use bookbeat::{
+    client::BookFormat,
+    locale::{Language, Market},
+    selection::{Downloads, Entry, Selections, State, Update},
+};
+use bookbeat_mock::MockServer;
+
+/* Both parts of Harbor Mysteries and the ebook-only Anna Bergman's Kitchen */
+async fn entries() -> Vec<Entry> {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+
+    let series = client.series_all(501).await.unwrap();
+    let mut entries: Vec<Entry> = series
+        ._embedded
+        .parts
+        .into_iter()
+        .map(|part| Entry {
+            book: part._embedded.book,
+            series: Some(series.name.clone()),
+            part: part.partnumber,
+        })
+        .collect();
+
+    let kitchen = client
+        .search_all(Some("Anna Berg"), None, &[Language::English], true)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|book| book.id == 1006)
+        .unwrap();
+    entries.push(Entry {
+        book: kitchen,
+        series: None,
+        part: None,
+    });
+    entries
+}
+
+fn isbns(selections: &mut Selections) -> Vec<String> {
+    selections
+        .take(Market::Germany)
+        .into_iter()
+        .map(|item| item.isbn)
+        .collect()
+}
+
+#[tokio::test]
+async fn toggle_picks_preferred_formats_or_what_the_book_has() {
+    let entries = entries().await;
+    let mut selections = Selections::default();
+
+    selections.toggle(&entries[0], &[BookFormat::EBook]);
+    let selection = selections.get(1001).unwrap();
+    assert!(!selection.audiobook && selection.ebook);
+
+    /* A second toggle unselects */
+    selections.toggle(&entries[0], &[BookFormat::EBook]);
+    assert!(selections.is_empty());
+
+    /* No ebook, so the audiobook */
+    selections.toggle(&entries[1], &[BookFormat::EBook]);
+    let selection = selections.get(1002).unwrap();
+    assert!(selection.audiobook && !selection.ebook);
+
+    /* Without preferences the audiobook, else the ebook */
+    selections.toggle(&entries[0], &[]);
+    selections.toggle(&entries[2], &[]);
+    assert!(selections.get(1001).unwrap().audiobook);
+    assert!(selections.get(1006).unwrap().ebook);
+
+    assert_eq!(
+        isbns(&mut selections),
+        ["9780000000035", "9780000000011", "9780000000103"]
+    );
+    assert!(selections.is_empty());
+}
+
+#[tokio::test]
+async fn toggle_format_adds_and_drops_formats() {
+    let entries = entries().await;
+    let mut selections = Selections::default();
+
+    assert!(!selections.toggle_format(&entries[1], BookFormat::EBook));
+    assert!(selections.is_empty());
+
+    assert!(selections.toggle_format(&entries[0], BookFormat::EBook));
+    assert!(selections.toggle_format(&entries[0], BookFormat::AudioBook));
+    let selection = selections.get(1001).unwrap();
+    assert!(selection.audiobook && selection.ebook);
+
+    /* The last format off unselects the book */
+    selections.toggle_format(&entries[0], BookFormat::EBook);
+    selections.toggle_format(&entries[0], BookFormat::AudioBook);
+    assert!(selections.get(1001).is_none());
+}
+
+#[tokio::test]
+async fn queued_items_are_named_like_planned_ones() {
+    let entries = entries().await;
+    let mut selections = Selections::default();
+    selections.toggle_format(&entries[0], BookFormat::AudioBook);
+    selections.toggle_format(&entries[0], BookFormat::EBook);
+    selections.toggle(&entries[2], &[]);
+
+    let items = selections.take(Market::Sweden);
+    let names: Vec<String> = items.iter().map(|item| item.file_name()).collect();
+    assert_eq!(
+        names,
+        [
+            "001 The Silent Harbor (9780000000011).m4a",
+            "001 The Silent Harbor (9780000000028).epub",
+            "Anna Bergman's Kitchen (9780000000103).epub",
+        ]
+    );
+    assert!(items.iter().all(|item| item.market == Market::Sweden));
+    assert!(items.iter().all(|item| !item.pinned));
+    assert_eq!(items[0].metadata().unwrap().part, Some(1));
+}
+
+#[tokio::test]
+async fn updates_go_to_the_unfinished_download_of_the_isbn() {
+    let entries = entries().await;
+    let mut selections = Selections::default();
+    let mut downloads = Downloads::default();
+
+    /* The same file queued twice, e.g. to retry it */
+    for _ in 0..2 {
+        selections.toggle(&entries[1], &[]);
+        for item in selections.take(Market::Germany) {
+            downloads.push(&item);
+        }
+    }
+    assert_eq!(downloads.len(), 2);
+    assert_eq!(downloads.unfinished(), 2);
+
+    let isbn = "9780000000035".to_owned();
+    downloads.update(Update::Progress(isbn.clone(), 5, 10));
+    assert_eq!(downloads.items()[0].state, State::Running(5, 10));
+
+    let failed = downloads
+        .update(Update::Finished(isbn.clone(), Err("gone".to_owned())))
+        .unwrap();
+    assert_eq!(
+        failed.file_name,
+        "002 The Silent Harbor: Tides (9780000000035).m4a"
+    );
+    assert_eq!(failed.state, State::Failed("gone".to_owned()));
+
+    downloads.update(Update::Finished(isbn.clone(), Ok(())));
+    assert_eq!(downloads.items()[1].state, State::Done);
+    assert_eq!(downloads.unfinished(), 0);
+
+    /* Deferred by the licensing limit, which leaves nothing unfinished */
+    selections.toggle(&entries[2], &[]);
+    for item in selections.take(Market::Germany) {
+        downloads.push(&item);
+    }
+    assert_eq!(downloads.unfinished(), 1);
+    downloads.update(Update::Deferred("9780000000103".to_owned()));
+    assert_eq!(downloads.items()[2].state, State::Deferred);
+    assert_eq!(downloads.unfinished(), 0);
+
+    /* Nothing left to update */
+    assert!(downloads.update(Update::Finished(isbn, Ok(()))).is_none());
+    assert!(downloads
+        .update(Update::Progress("9789999999999".to_owned(), 1, 2))
+        .is_none());
+}
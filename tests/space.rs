@@ -0,0 +1,92 @@
+use bookbeat::{
+    api::Error,
+    client::BookFormat,
+    download::{self, Downloader},
+    space::{self, Size},
+};
+use bookbeat_mock::MockServer;
+use std::sync::atomic::Ordering;
+
+#[test]
+fn sizes_parse_and_print() {
+    assert_eq!("1500".parse(), Ok(Size(1500)));
+    assert_eq!("500k".parse(), Ok(Size(500 << 10)));
+    assert_eq!("2G".parse(), Ok(Size(2 << 30)));
+    assert_eq!("0".parse(), Ok(Size(0)));
+    assert!("-1M".parse::<Size>().is_err());
+    assert!("lots".parse::<Size>().is_err());
+
+    assert_eq!(Size(512).to_string(), "512 B");
+    assert_eq!(Size(1536 << 10).to_string(), "1.5 MiB");
+}
+
+#[test]
+fn check_keeps_the_reserve() {
+    let dir = tempfile::tempdir().unwrap();
+    let free = space::available(dir.path()).unwrap();
+    assert!(space::check(dir.path(), 0, 0, "for nothing").is_ok());
+
+    /* Folders yet to be created are asked about their closest parent */
+    let missing = dir.path().join("Anna Berg").join("The Silent Harbor");
+    assert!(space::check(&missing, 0, 0, "for nothing").is_ok());
+
+    let err = space::check(dir.path(), 1, free, "for \"book.m4a\"").unwrap_err();
+    let Error::DiskSpace(message) = err else {
+        panic!("{err:?}");
+    };
+    assert!(message.contains("needed for \"book.m4a\""), "{message}");
+}
+
+#[tokio::test]
+async fn downloads_stop_before_the_reserve() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let options = download::Options {
+        reserve: u64::MAX / 2,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    assert!(matches!(
+        downloader.check_space(&[BookFormat::AudioBook, BookFormat::EBook]),
+        Err(Error::DiskSpace(_))
+    ));
+
+    let result = downloader
+        .download(
+            &client,
+            "9780000000011",
+            BookFormat::AudioBook,
+            None,
+            "book.m4a",
+        )
+        .await;
+    assert!(matches!(result, Err(Error::DiskSpace(_))));
+    assert!(!dir.path().join("book.m4a").exists());
+
+    /* Stopped by the estimate before a license was spent */
+    assert_eq!(server.state.licenses.load(Ordering::SeqCst), 0);
+
+    /* Without trusting the estimate only the licensed size stops it */
+    let options = download::Options {
+        reserve: u64::MAX / 2,
+        ignore_estimate: true,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+    assert!(downloader.check_space(&[BookFormat::AudioBook]).is_ok());
+
+    let result = downloader
+        .download(
+            &client,
+            "9780000000011",
+            BookFormat::AudioBook,
+            None,
+            "book.m4a",
+        )
+        .await;
+    assert!(matches!(result, Err(Error::DiskSpace(_))));
+    assert_eq!(server.state.licenses.load(Ordering::SeqCst), 1);
+}
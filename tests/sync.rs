@@ -0,0 +1,140 @@
+use bookbeat::{
+    client::BookFormat,
+    download::{self, Downloader, Stop},
+    filter::Content,
+    locale::Language,
+    queue::Queue,
+    sync::{self, Settings},
+    watchlist::{Target, Watch, Watchlist},
+};
+use bookbeat_mock::MockServer;
+use std::sync::atomic::Ordering;
+
+#[tokio::test]
+async fn sync_downloads_only_what_is_missing() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(dir.path().to_owned(), download::Options::default()).unwrap();
+
+    /* The first part of Harbor Mysteries is already there */
+    downloader
+        .download(
+            &client,
+            "9780000000011",
+            BookFormat::AudioBook,
+            None,
+            "001 The Silent Harbor (9780000000011).m4a",
+        )
+        .await
+        .unwrap();
+
+    let mut watchlist = Watchlist::load(&dir.path().join("watchlist.json")).unwrap();
+    watchlist.add(Watch::new(Target::Series(501)));
+    watchlist.add(Watch::new(Target::Author("Anna Berg".to_owned())));
+    let settings = |_: &Watch| Settings {
+        formats: vec![BookFormat::AudioBook],
+        content: Content {
+            languages: vec![Language::English],
+            sfw: false,
+        },
+    };
+
+    let missing = sync::missing(
+        &client,
+        &downloader,
+        &watchlist.entries[0],
+        &settings(&watchlist.entries[0]),
+    )
+    .await
+    .unwrap();
+    let names: Vec<String> = missing.iter().map(|item| item.file_name()).collect();
+    assert_eq!(names, ["002 The Silent Harbor: Tides (9780000000035).m4a"]);
+
+    /* The author's books of the series were just synced with it */
+    let new = sync::run(&client, &downloader, &mut watchlist, settings)
+        .await
+        .unwrap();
+    assert_eq!(new.completed.len(), 2);
+    assert!(new.stopped.is_none());
+    assert_eq!(server.state.licenses.load(Ordering::SeqCst), 3);
+    assert!(dir
+        .path()
+        .join("002 The Silent Harbor: Tides (9780000000035).m4a")
+        .exists());
+    assert!(dir.path().join("Night Trains (9780000000042).m4a").exists());
+
+    let mut watchlist = Watchlist::load(&dir.path().join("watchlist.json")).unwrap();
+    assert!(watchlist
+        .entries
+        .iter()
+        .all(|watch| watch.last_sync.is_some()));
+
+    /* Nothing new the second time */
+    let new = sync::run(&client, &downloader, &mut watchlist, settings)
+        .await
+        .unwrap();
+    assert!(new.completed.is_empty());
+    assert_eq!(server.state.licenses.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn stopped_sync_leaves_the_entries_for_the_next_one() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(dir.path().to_owned(), download::Options::default()).unwrap();
+
+    let mut watchlist = Watchlist::load(&dir.path().join("watchlist.json")).unwrap();
+    watchlist.add(Watch::new(Target::Series(501)));
+
+    /* Ctrl-C before the first entry */
+    downloader.cancel().stop();
+    let batch = sync::run(&client, &downloader, &mut watchlist, |_| Settings {
+        formats: vec![BookFormat::AudioBook],
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert_eq!(batch.stopped, Some(Stop::Cancelled));
+    assert!(batch.completed.is_empty());
+    assert!(watchlist.entries[0].last_sync.is_none());
+    assert_eq!(server.state.licenses.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn licensing_limit_queues_the_rest_of_the_entry() {
+    let server = MockServer::with_quota(1).await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(dir.path().to_owned(), download::Options::default()).unwrap();
+
+    let mut watchlist = Watchlist::load(&dir.path().join("watchlist.json")).unwrap();
+    watchlist.add(Watch::new(Target::Series(501)));
+    watchlist.add(Watch::new(Target::Author("Anna Berg".to_owned())));
+
+    let batch = sync::run(&client, &downloader, &mut watchlist, |_| Settings {
+        formats: vec![BookFormat::AudioBook],
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert_eq!(batch.stopped, Some(Stop::LimitReached));
+    assert_eq!(batch.completed.len(), 1);
+    let pending: Vec<&str> = batch
+        .pending
+        .iter()
+        .map(|item| item.isbn.as_str())
+        .collect();
+    assert_eq!(pending, ["9780000000035"]);
+    assert!(watchlist
+        .entries
+        .iter()
+        .all(|watch| watch.last_sync.is_none()));
+
+    downloader.settle(&client, &batch).unwrap();
+    let queue = Queue::load(dir.path()).unwrap();
+    assert_eq!(queue.items()[0].isbn, "9780000000035");
+    assert_eq!(queue.items()[0].part, Some(2));
+    assert!(queue.reset.is_some());
+}
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use bookbeat::{
+    client::BookFormat,
+    download::{self, Batch, Downloader, Stop},
+    locale::Market,
+    plan::Plan,
+    queue::Queue,
+    throttle::{Rate, RateLimit, Window},
+};
+use bookbeat_mock::{MockServer, AUDIOBOOK};
+use chrono::NaiveTime;
+use tokio::time::Instant;
+
+fn time(time: &str) -> NaiveTime {
+    NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+}
+
+#[test]
+fn rates_and_windows_parse() {
+    assert_eq!("1500".parse(), Ok(Rate(1500)));
+    assert_eq!("500k".parse(), Ok(Rate(500 * 1024)));
+    assert_eq!("1.5M".parse(), Ok(Rate(1536 * 1024)));
+    assert!("0".parse::<Rate>().is_err());
+    assert!("fast".parse::<Rate>().is_err());
+
+    let night: Window = "22:00-06:00".parse().unwrap();
+    assert_eq!(night.to_string(), "22:00-06:00");
+    assert!("22:00".parse::<Window>().is_err());
+    assert!("25:00-06:00".parse::<Window>().is_err());
+}
+
+#[test]
+fn windows_may_span_midnight() {
+    let night: Window = "22:00-06:00".parse().unwrap();
+    assert!(night.contains(time("23:30")));
+    assert!(night.contains(time("05:59")));
+    assert!(!night.contains(time("06:00")));
+    assert_eq!(night.until_open(time("01:00")), Duration::ZERO);
+    assert_eq!(
+        night.until_open(time("20:30")),
+        Duration::from_secs(90 * 60)
+    );
+
+    let lunch: Window = "12:00-13:00".parse().unwrap();
+    assert!(!lunch.contains(time("23:00")));
+    assert_eq!(
+        lunch.until_open(time("13:00")),
+        Duration::from_secs(23 * 3600)
+    );
+}
+
+#[tokio::test]
+async fn rate_limit_is_shared() {
+    let limit = RateLimit::new(Rate(10_000));
+    let start = Instant::now();
+
+    /* Two transfers of 1000 bytes each take a fifth of a second together */
+    tokio::join!(limit.take(1000), limit.take(1000));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(190), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+}
+
+#[tokio::test]
+async fn downloads_are_throttled() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    /* The whole file within about a fifth of a second */
+    let options = download::Options {
+        limit_rate: Some(Rate(AUDIOBOOK.len() as u64 * 5)),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(dir.path().to_owned(), options).unwrap();
+
+    let start = std::time::Instant::now();
+    downloader
+        .download(
+            &client,
+            "9780000000011",
+            BookFormat::AudioBook,
+            None,
+            "book.m4a",
+        )
+        .await
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(190));
+}
+
+#[tokio::test]
+async fn closed_window_queues_the_rest() {
+    let server = MockServer::start().await;
+    let client = server.client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(dir.path().to_owned(), download::Options::default()).unwrap();
+
+    let mut plan = Plan::default();
+    for id in [1001, 1002] {
+        let book = client.books(id).await.unwrap();
+        plan.add_book(&book, BookFormat::AudioBook, Market::Germany);
+    }
+
+    /* As left by a transfer that ran past the window */
+    let batch = Batch {
+        pending: plan.items().to_vec(),
+        stopped: Some(Stop::WindowClosed),
+        ..Default::default()
+    };
+    downloader.settle(&client, &batch).unwrap();
+
+    let queue = Queue::load(dir.path()).unwrap();
+    assert_eq!(queue.len(), 2);
+    assert!(queue.reset.is_none(), "no licensing limit to wait for");
+    assert!(!dir
+        .path()
+        .join("The Silent Harbor (9780000000011).m4a")
+        .exists());
+}
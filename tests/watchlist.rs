@@ -0,0 +1,68 @@
+use bookbeat::{
+    locale::Language,
+    watchlist::{Target, Watch, Watchlist},
+};
+
+#[test]
+fn entries_are_added_once_and_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("watchlist.json");
+
+    /* A missing file is an empty list */
+    let mut watchlist = Watchlist::load(&path).unwrap();
+    assert!(watchlist.entries.is_empty());
+
+    let mut anna = Watch::new(Target::Author("Anna Berg".to_owned()));
+    anna.languages = vec![Language::English];
+    assert!(watchlist.add(anna));
+    assert!(watchlist.add(Watch::new(Target::Series(501))));
+    assert!(!watchlist.add(Watch::new(Target::Author("Anna Berg".to_owned()))));
+    watchlist.save().unwrap();
+
+    let mut watchlist = Watchlist::load(&path).unwrap();
+    let targets: Vec<&Target> = watchlist
+        .entries
+        .iter()
+        .map(|watch| &watch.target)
+        .collect();
+    assert_eq!(
+        targets,
+        [
+            &Target::Author("Anna Berg".to_owned()),
+            &Target::Series(501)
+        ]
+    );
+    assert_eq!(watchlist.entries[0].languages, [Language::English]);
+
+    assert!(watchlist.remove(&Target::Author("Anna Berg".to_owned())));
+    assert!(!watchlist.remove(&Target::Narrator("Anna Berg".to_owned())));
+    watchlist.save().unwrap();
+
+    let watchlist = Watchlist::load(&path).unwrap();
+    assert_eq!(watchlist.entries.len(), 1);
+    assert_eq!(watchlist.entries[0].target, Target::Series(501));
+    assert!(!dir.path().join("watchlist.json.tmp").exists());
+}
+
+#[test]
+fn entries_are_stored_by_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("watchlist.json");
+    std::fs::write(
+        &path,
+        r#"[{"narrator": "Lukas Hahn", "ebook": true}, {"series": 502}]"#,
+    )
+    .unwrap();
+
+    let watchlist = Watchlist::load(&path).unwrap();
+    assert_eq!(
+        watchlist.entries[0].target,
+        Target::Narrator("Lukas Hahn".to_owned())
+    );
+    assert_eq!(watchlist.entries[0].ebook, Some(true));
+    assert_eq!(watchlist.entries[0].audiobook, None);
+    assert_eq!(watchlist.entries[1].target.to_string(), "series 502");
+
+    std::fs::write(&path, "not json").unwrap();
+    assert!(Watchlist::load(&path).is_err());
+}